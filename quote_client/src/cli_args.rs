@@ -1,4 +1,19 @@
-use clap::Parser;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use quote_generator_lib::core::RecordFormat;
+
+use crate::output::OutputFormat;
+
+/// How quotes travel from the server to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Transport {
+    Udp,
+    /// Reliable and NAT friendly, the client connects to the server
+    Tcp,
+}
 
 #[derive(Parser)]
 #[command(name = "quote_client")]
@@ -10,9 +25,64 @@ pub struct CliArgs {
     #[arg(short, long)]
     pub port: u16,
 
-    #[arg(short = 'A', long)]
-    pub stream_addr: String,
+    /// UDP address to receive quotes on, not needed for TCP and multicast
+    #[arg(short = 'A', long, required_unless_present_any = ["multicast", "transport"])]
+    pub stream_addr: Option<String>,
+
+    /// `udp` streams quotes to `--stream_addr`, `tcp` receives them over a TCP connection opened to the server
+    #[arg(long, value_enum, default_value_t = Transport::Udp, conflicts_with = "multicast", requires_if("udp", "stream_addr"))]
+    pub transport: Transport,
+
+    /// Join the server multicast channels of the tickers instead of asking for a UDP stream of our own
+    #[arg(long, conflicts_with = "stream_addr")]
+    pub multicast: bool,
+
+    /// Local interface to join multicast groups on, e.g. 127.0.0.1 for loopback only
+    #[arg(long, default_value = "0.0.0.0", requires = "multicast")]
+    pub multicast_interface: Ipv4Addr,
 
     #[arg(short = 'T', long)]
     pub tickers: String,
+
+    /// Ask the server to send at most this many quotes per second, holding back the rest
+    #[arg(long, conflicts_with = "multicast")]
+    pub max_rate: Option<u32>,
+
+    /// Ask the server to send at most one quote per ticker in this many milliseconds
+    #[arg(long, conflicts_with = "multicast")]
+    pub throttle_ms: Option<u64>,
+
+    /// Format of the quotes printed on stdout, status messages always go to stderr
+    #[arg(short = 'F', long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Full-screen view with one row per ticker updated in place, instead of printing every quote
+    #[arg(short = 'D', long, conflicts_with = "format")]
+    pub dashboard: bool,
+
+    /// Record every received quote, files rotate daily: `quotes.csv` becomes `quotes-YYYY-MM-DD.csv`
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Record format: `csv` (pipe-delimited quotes) or `bin` (length-prefixed bincode), by extension when not given
+    #[arg(long, requires = "record")]
+    pub record_format: Option<RecordFormat>,
+
+    /// Also rotate the record file once it grows beyond this many bytes
+    #[arg(long, requires = "record")]
+    pub record_max_bytes: Option<u64>,
+}
+
+impl CliArgs {
+    /// Options appended to the STREAM command, empty when no limit is asked for
+    pub fn stream_options(&self) -> String {
+        let mut options = String::new();
+        if let Some(rate) = self.max_rate {
+            options.push_str(&format!(" MAXRATE={}/s", rate));
+        }
+        if let Some(throttle) = self.throttle_ms {
+            options.push_str(&format!(" THROTTLE={}ms", throttle));
+        }
+        options
+    }
 }
@@ -0,0 +1,82 @@
+use std::io::{self, BufRead};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::control::StreamControl;
+use crate::sequence_tracker::SequenceTracker;
+
+/// Sequence tracker of one stream, labelled with the address quotes come from
+pub type NamedTracker = (String, Arc<Mutex<SequenceTracker>>);
+
+/// Prints the sequence counters of every stream on stderr
+pub fn print_stats(trackers: &[NamedTracker]) {
+    for (name, tracker) in trackers {
+        if let Ok(tracker) = tracker.lock() {
+            status!("Stream stats {}: {}", name, tracker);
+        }
+    }
+}
+
+/// Reads commands typed on stdin while quotes are streaming
+///
+/// `stats` prints the sequence counters, `subscribe AAPL,MSFT` and `unsubscribe TSLA` change the tickers
+/// of the `stream` session, `sessions` lists the streams of its connection, `help` lists the commands. The thread ends with stdin.
+pub fn start(trackers: Vec<NamedTracker>, stream: Option<StreamControl>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or_default().to_lowercase();
+            match (command.as_str(), words.next()) {
+                ("", _) => {}
+                ("stats", None) => print_stats(&trackers),
+                ("help", None) => status!("Commands: stats, subscribe <TICKERS>, unsubscribe <TICKERS>, sessions, help"),
+                ("sessions", None) => list_sessions(stream.as_ref()),
+                ("subscribe" | "unsubscribe", Some(tickers)) => change_tickers(stream.as_ref(), &command, tickers),
+                ("subscribe" | "unsubscribe", None) => {
+                    status!("Use like '{} AAPL,TSLA'", command)
+                }
+                _ => status!("Unknown command '{}', type help", line.trim()),
+            }
+        }
+    });
+}
+
+/// Prints the streams the server runs for the control connection
+fn list_sessions(stream: Option<&StreamControl>) {
+    let Some((control, _)) = stream else {
+        status!("Multicast has no sessions on the server");
+        return;
+    };
+    let result = match control.lock() {
+        Ok(mut control) => control.list(),
+        Err(_) => return,
+    };
+    match result {
+        Ok(sessions) if sessions.is_empty() => status!("No sessions"),
+        Ok(sessions) => {
+            for session in sessions {
+                status!("Session {}", session);
+            }
+        }
+        Err(e) => status!("sessions failed: {}", e),
+    }
+}
+
+/// Sends a subscription change to the server and reports the tickers streamed from now on
+fn change_tickers(stream: Option<&StreamControl>, command: &str, tickers: &str) {
+    let Some((control, session)) = stream else {
+        status!("Multicast channels are fixed, restart with other tickers instead");
+        return;
+    };
+    let tickers = tickers.to_uppercase();
+    let result = match control.lock() {
+        Ok(mut control) if command == "subscribe" => control.subscribe(*session, &tickers),
+        Ok(mut control) => control.unsubscribe(*session, &tickers),
+        Err(_) => return,
+    };
+    match result {
+        Ok(streamed) => status!("Streaming tickers: {}", streamed.join(",")),
+        Err(e) => status!("{} failed: {}", command, e),
+    }
+}
@@ -0,0 +1,209 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use quote_generator_lib::core::{InstrumentSpec, SequencedQuote, StockQuote};
+
+const TCP_KEEPALIVE_TIME_SECS: u64 = 10;
+const TCP_KEEPALIVE_INTERVAL_SECS: u64 = 5;
+const TCP_READ_TIMEOUT_SECS: u64 = 5;
+
+/// Control connection of a stream and the ID of the stream session on it
+pub type StreamControl = (Arc<Mutex<ControlClient>>, u64);
+
+/// TCP control connection to the quote streamer
+pub struct ControlClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl ControlClient {
+    // Подключение к серверу
+    pub fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+
+        socket.set_keepalive(true)?;
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            socket.set_tcp_keepalive(
+                &socket2::TcpKeepalive::new()
+                    .with_time(Duration::from_secs(TCP_KEEPALIVE_TIME_SECS))
+                    .with_interval(Duration::from_secs(TCP_KEEPALIVE_INTERVAL_SECS)),
+            )?;
+        }
+
+        let addr: SocketAddr = format!("{}:{}", host, port)
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        socket.connect(&addr.into())?;
+
+        let stream: TcpStream = socket.into();
+        stream.set_read_timeout(Some(Duration::from_secs(TCP_READ_TIMEOUT_SECS)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        // Читаем welcome message один раз
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        eprint!("{}", line);
+
+        status!("Connected to server!");
+        Ok(Self { stream, reader })
+    }
+
+    // Отправка команды
+    pub fn send_command(&mut self, command: &str) -> io::Result<String> {
+        self.stream.write_all(command.as_bytes())?;
+        self.stream.write_all(b"\n")?;
+        self.stream.flush()?;
+        self.read_line()
+    }
+
+    /// Asks the server to send quotes `from..=to` of a stream of this connection again
+    ///
+    /// Returns the quotes the server still had, possibly fewer than asked for
+    pub fn resend(&mut self, session: u64, from: u64, to: u64) -> io::Result<Vec<SequencedQuote>> {
+        let header = self.send_command(&format!("RESEND {} {} {}", session, from, to))?;
+        let count = parse_resend_header(&header)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, header.trim().to_string()))?;
+
+        let mut quotes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let line = self.read_line()?;
+            let quote = SequencedQuote::from_string(line.trim()).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid resent quote '{}'", line.trim()))
+            })?;
+            quotes.push(quote);
+        }
+        Ok(quotes)
+    }
+
+    /// Asks the server for the last quote of each of the comma-separated tickers
+    ///
+    /// Tickers the server has no quote of yet are left out
+    pub fn snapshot(&mut self, tickers: &str) -> io::Result<Vec<StockQuote>> {
+        let header = self.send_command(&format!("SNAPSHOT {}", tickers))?;
+        let count = parse_snapshot_header(&header)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, header.trim().to_string()))?;
+
+        let mut quotes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let line = self.read_line()?;
+            let quote = StockQuote::from_string(line.trim()).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid snapshot quote '{}'", line.trim()))
+            })?;
+            quotes.push(quote);
+        }
+        Ok(quotes)
+    }
+
+    /// Asks the server for the price rules of each of the comma-separated tickers
+    pub fn specs(&mut self, tickers: &str) -> io::Result<HashMap<String, InstrumentSpec>> {
+        let header = self.send_command(&format!("SPECS {}", tickers))?;
+        let count = header
+            .strip_prefix("SPECS count: ")
+            .and_then(|count| count.trim().parse::<usize>().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, header.trim().to_string()))?;
+
+        let mut specs = HashMap::with_capacity(count);
+        for _ in 0..count {
+            let line = self.read_line()?;
+            let (ticker, spec) = parse_spec_line(line.trim()).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid price rules '{}': {}", line.trim(), e))
+            })?;
+            specs.insert(ticker, spec);
+        }
+        Ok(specs)
+    }
+
+    /// Adds comma-separated tickers to a stream of this connection
+    ///
+    /// Returns the tickers the server streams from now on
+    pub fn subscribe(&mut self, session: u64, tickers: &str) -> io::Result<Vec<String>> {
+        self.change_tickers("SUBSCRIBE", session, tickers)
+    }
+
+    /// Removes comma-separated tickers from a stream of this connection
+    ///
+    /// Returns the tickers the server streams from now on
+    pub fn unsubscribe(&mut self, session: u64, tickers: &str) -> io::Result<Vec<String>> {
+        self.change_tickers("UNSUBSCRIBE", session, tickers)
+    }
+
+    /// Stops a stream of this connection right away, instead of letting it time out
+    pub fn stop(&mut self, session: u64) -> io::Result<()> {
+        let reply = self.send_command(&format!("STOP {}", session))?;
+        if reply.trim() == format!("STOPPED {}", session) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, reply.trim().to_string()))
+        }
+    }
+
+    /// Returns one line per stream started on this connection: `<id> <state> server: <addr> tickers: <tickers>`
+    pub fn list(&mut self) -> io::Result<Vec<String>> {
+        let header = self.send_command("LIST")?;
+        let count = header
+            .strip_prefix("SESSIONS count: ")
+            .and_then(|count| count.trim().parse::<usize>().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, header.trim().to_string()))?;
+
+        (0..count).map(|_| self.read_line().map(|line| line.trim().to_string())).collect()
+    }
+
+    fn change_tickers(&mut self, command: &str, session: u64, tickers: &str) -> io::Result<Vec<String>> {
+        let reply = self.send_command(&format!("{} {} {}", command, session, tickers))?;
+        parse_tickers_reply(&reply).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, reply.trim().to_string()))
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut buffer = String::new();
+        let bytes = self.reader.read_line(&mut buffer)?;
+        if bytes == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Server closed connection",
+            ));
+        }
+        Ok(buffer)
+    }
+}
+
+/// Returns the number of quotes announced by a `RESEND <from> <to> count: <n>` header
+pub fn parse_resend_header(header: &str) -> Option<usize> {
+    header
+        .strip_prefix("RESEND ")?
+        .split("count: ")
+        .nth(1)?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Returns the number of quotes announced by a `SNAPSHOT count: <n>` header
+pub fn parse_snapshot_header(header: &str) -> Option<usize> {
+    header.strip_prefix("SNAPSHOT count: ")?.trim().parse().ok()
+}
+
+/// Parses a `ticker|tick_size|precision` line of a SPECS reply
+pub fn parse_spec_line(line: &str) -> Result<(String, InstrumentSpec), String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    let [ticker, tick_size, precision] = fields[..] else {
+        return Err("expected ticker|tick_size|precision".to_string());
+    };
+    let precision = precision.parse::<u8>().map_err(|e| e.to_string())?;
+    Ok((ticker.to_string(), InstrumentSpec::new(tick_size.parse()?, precision)?))
+}
+
+/// Returns the session ID at the end of a STREAM response, `... server: <addr> session: <id>`
+pub fn parse_session_id(response: &str) -> Option<u64> {
+    response.split("session: ").nth(1)?.split_whitespace().next()?.parse().ok()
+}
+
+/// Returns the tickers listed by a `TICKERS AAPL,MSFT` reply
+pub fn parse_tickers_reply(reply: &str) -> Option<Vec<String>> {
+    let tickers = reply.trim_end().strip_prefix("TICKERS")?;
+    Some(tickers.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()).map(|t| t.to_string()).collect())
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use quote_generator_lib::core::{Price, StockQuote};
+use quote_generator_lib::timestamp;
+
+use crate::output::{PriceFormat, QuoteSink};
+use crate::status;
+
+const REFRESH_INTERVAL_MS: u64 = 250;
+/// Window the update rate is averaged over
+const RATE_WINDOW_SECS: u64 = 10;
+
+// Управляющие последовательности ANSI для полноэкранного режима
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+const CURSOR_HOME: &str = "\x1b[H";
+const CLEAR_LINE: &str = "\x1b[K";
+const CLEAR_BELOW: &str = "\x1b[J";
+
+/// Statistics of one subscribed ticker
+struct TickerStats {
+    first_price: Price,
+    last: StockQuote,
+    updates: u64,
+    recent: VecDeque<Instant>,
+    last_update: Instant,
+}
+
+/// Everything the dashboard shows, one row per subscribed ticker
+pub struct DashboardState {
+    tickers: Vec<String>,
+    stats: HashMap<String, TickerStats>,
+    prices: PriceFormat,
+    started: Instant,
+}
+
+impl DashboardState {
+    /// Creates an empty state for the subscribed tickers, prices are printed with the precision of `prices`
+    pub fn new(tickers: &[String], prices: PriceFormat, started: Instant) -> Self {
+        Self {
+            tickers: tickers.to_vec(),
+            stats: HashMap::new(),
+            prices,
+            started,
+        }
+    }
+
+    /// Updates the row of the quote ticker, tickers outside the subscription get a row of their own
+    pub fn update(&mut self, quote: &StockQuote, now: Instant) {
+        self.record(quote, now, false);
+    }
+
+    /// Counts a quote delivered after newer ones, the row keeps showing the newer prices
+    pub fn update_late(&mut self, quote: &StockQuote, now: Instant) {
+        self.record(quote, now, true);
+    }
+
+    fn record(&mut self, quote: &StockQuote, now: Instant, late: bool) {
+        if !self.tickers.contains(&quote.ticker) {
+            self.tickers.push(quote.ticker.clone());
+        }
+
+        let stats = self.stats.entry(quote.ticker.clone()).or_insert_with(|| TickerStats {
+            first_price: quote.price,
+            last: quote.clone(),
+            updates: 0,
+            recent: VecDeque::new(),
+            last_update: now,
+        });
+        if !late {
+            stats.last = quote.clone();
+        }
+        stats.updates += 1;
+        stats.last_update = now;
+        stats.recent.push_back(now);
+        while let Some(oldest) = stats.recent.front()
+            && now.duration_since(*oldest) > Duration::from_secs(RATE_WINDOW_SECS)
+        {
+            stats.recent.pop_front();
+        }
+    }
+
+    /// Renders the screen lines as of `now`
+    pub fn render(&self, now: Instant) -> Vec<String> {
+        let mut lines = Vec::with_capacity(self.tickers.len() + 3);
+        lines.push(format!(
+            "[{}] Quote Client dashboard, {} tickers, up {}s, Ctrl+C to quit",
+            timestamp(),
+            self.tickers.len(),
+            now.duration_since(self.started).as_secs()
+        ));
+        lines.push(String::new());
+        lines.push(format!(
+            "{:<8} {:>12} {:>10} {:>8} {:>12} {:>12} {:>12} {:>8} {:>8}",
+            "TICKER", "LAST", "CHANGE", "CHG %", "BID", "ASK", "VOLUME", "UPD/S", "AGE S"
+        ));
+
+        // Частота обновлений усредняется по окну, но не дольше времени работы
+        let window = now
+            .duration_since(self.started)
+            .min(Duration::from_secs(RATE_WINDOW_SECS))
+            .as_secs_f64()
+            .max(1.0);
+
+        for ticker in &self.tickers {
+            let line = match self.stats.get(ticker) {
+                Some(stats) => {
+                    let change = stats.last.price - stats.first_price;
+                    let change_pct = if stats.first_price > Price::ZERO {
+                        change.to_f64() / stats.first_price.to_f64() * 100.0
+                    } else {
+                        0.0
+                    };
+                    let recent = stats
+                        .recent
+                        .iter()
+                        .filter(|t| now.duration_since(**t) <= Duration::from_secs(RATE_WINDOW_SECS))
+                        .count();
+                    format!(
+                        "{:<8} {:>12} {:>10} {:>7.2}% {:>12} {:>12} {:>12} {:>8.2} {:>8.1}",
+                        ticker,
+                        self.prices.format(ticker, stats.last.price),
+                        format!("{}{}", if change > Price::ZERO { "+" } else { "" }, self.prices.format(ticker, change)),
+                        change_pct,
+                        self.prices.format(ticker, stats.last.bid),
+                        self.prices.format(ticker, stats.last.ask),
+                        stats.last.volume,
+                        recent as f64 / window,
+                        now.duration_since(stats.last_update).as_secs_f64()
+                    )
+                }
+                None => format!(
+                    "{:<8} {:>12} {:>10} {:>8} {:>12} {:>12} {:>12} {:>8} {:>8}",
+                    ticker, "-", "-", "-", "-", "-", "-", "-", "-"
+                ),
+            };
+            lines.push(line);
+        }
+
+        let updates: u64 = self.stats.values().map(|s| s.updates).sum();
+        lines.push(String::new());
+        lines.push(format!("{} updates received", updates));
+        lines
+    }
+}
+
+/// Full-screen view keeping one row per subscribed ticker, redrawn in place
+///
+/// Status messages go to the status line under the table while the dashboard is shown,
+/// the terminal is restored and the last of them is printed when the dashboard is dropped
+pub struct Dashboard {
+    state: Arc<Mutex<DashboardState>>,
+    running: Arc<AtomicBool>,
+    renderer: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Switches the terminal to the dashboard and starts redrawing it
+    pub fn start(tickers: &[String], prices: PriceFormat) -> Self {
+        let state = Arc::new(Mutex::new(DashboardState::new(tickers, prices, Instant::now())));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let state_clone = Arc::clone(&state);
+        let running_clone = Arc::clone(&running);
+        // Сообщения поверх таблицы портят экран, пока он показан, они идут в строку статуса
+        status::capture();
+        let renderer = thread::spawn(move || {
+            print!("{}", ENTER_SCREEN);
+            while running_clone.load(Ordering::Relaxed) {
+                let mut lines = match state_clone.lock() {
+                    Ok(state) => state.render(Instant::now()),
+                    Err(_) => break,
+                };
+                let status = status::current();
+                if !status.is_empty() {
+                    lines.push(status);
+                }
+                if draw(&lines).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(REFRESH_INTERVAL_MS));
+            }
+            print!("{}", LEAVE_SCREEN);
+            let _ = io::stdout().flush();
+        });
+
+        Self {
+            state,
+            running,
+            renderer: Some(renderer),
+        }
+    }
+}
+
+/// Redraws the screen from the top, clearing what is left of the previous frame
+fn draw(lines: &[String]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{}", CURSOR_HOME)?;
+    for line in lines {
+        write!(stdout, "{}{}\r\n", line, CLEAR_LINE)?;
+    }
+    write!(stdout, "{}", CLEAR_BELOW)?;
+    stdout.flush()
+}
+
+impl QuoteSink for Dashboard {
+    fn on_quote(&mut self, quote: &StockQuote) -> io::Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("dashboard state poisoned"))?;
+        state.update(quote, Instant::now());
+        Ok(())
+    }
+
+    fn on_late_quote(&mut self, quote: &StockQuote) -> io::Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("dashboard state poisoned"))?;
+        state.update_late(quote, Instant::now());
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "dashboard"
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(renderer) = self.renderer.take() {
+            let _ = renderer.join();
+        }
+        if let Some(last) = status::release() {
+            eprintln!("{}", last);
+        }
+    }
+}
@@ -1,77 +1,42 @@
-use socket2::{Domain, Protocol, Socket, Type};
-use std::io::{self, BufRead, BufReader, Write};
-use std::net::{SocketAddr, TcpStream};
-use std::sync::Arc;
+use std::io;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use std::time::{Duration};
-
 use clap::Parser;
-use quote_generator_lib::timestamp;
+use quote_generator_lib::core::RecordFormat;
+
+use crate::cli_args::Transport;
+use crate::control::{ControlClient, StreamControl, parse_session_id};
+use crate::dashboard::Dashboard;
+use crate::multicast_receiver::{MulticastReceiver, parse_multicast_response};
+use crate::output::{PriceFormat, QuoteSink, StdoutSink};
+use crate::quote_recorder::QuoteRecorder;
+use crate::quote_tcp_receiver::TcpQuoteReceiver;
+use crate::quote_udp_receiver::deliver;
+use crate::sequence_tracker::SequenceTracker;
+
+/// Reports a client status message, on stderr or on the dashboard status line while it is shown
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::status::report(&format!($($arg)*))
+    };
+}
 
 mod cli_args;
+mod console;
+mod control;
+mod dashboard;
+mod multicast_receiver;
+mod output;
+mod quote_recorder;
+mod quote_tcp_receiver;
 mod quote_udp_receiver;
+mod sequence_tracker;
+mod status;
 
 #[cfg(test)]
 mod tests;
 
-const TCP_KEEPALIVE_TIME_SECS: u64 = 10;
-const TCP_KEEPALIVE_INTERVAL_SECS: u64 = 5;
-const TCP_READ_TIMEOUT_SECS: u64 = 5;
-
-// Подключение к серверу
-fn connect(host: &str, port: u16) -> io::Result<(TcpStream, BufReader<TcpStream>)> {
-    let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
-
-    socket.set_keepalive(true)?;
-    #[cfg(any(target_os = "linux", target_os = "macos"))]
-    {
-        socket.set_tcp_keepalive(
-            &socket2::TcpKeepalive::new()
-                .with_time(Duration::from_secs(TCP_KEEPALIVE_TIME_SECS))
-                .with_interval(Duration::from_secs(TCP_KEEPALIVE_INTERVAL_SECS)),
-        )?;
-    }
-
-    let addr: SocketAddr = format!("{}:{}", host, port)
-        .parse()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-    socket.connect(&addr.into())?;
-
-    let stream: TcpStream = socket.into();
-    stream.set_read_timeout(Some(Duration::from_secs(TCP_READ_TIMEOUT_SECS)))?;
-    let mut reader = BufReader::new(stream.try_clone()?);
-
-    // Читаем welcome message один раз
-    let mut line = String::new();
-    reader.read_line(&mut line)?;
-    print!("{}", line);
-
-    println!("[{}] Connected to server!", timestamp());
-    Ok((stream, reader))
-}
-
-// Отправка команды
-fn send_command(
-    stream: &mut TcpStream,
-    reader: &mut BufReader<TcpStream>,
-    command: &str,
-) -> io::Result<String> {
-    stream.write_all(command.as_bytes())?;
-    stream.write_all(b"\n")?;
-    stream.flush()?;
-
-    let mut buffer = String::new();
-    let bytes = reader.read_line(&mut buffer)?;
-    if bytes == 0 {
-        return Err(io::Error::new(
-            io::ErrorKind::UnexpectedEof,
-            "Server closed connection",
-        ));
-    }
-    Ok(buffer)
-}
-
 fn main() -> io::Result<()> {
     let cli = cli_args::CliArgs::parse();
     
@@ -80,40 +45,198 @@ fn main() -> io::Result<()> {
     let shutdown_clone = Arc::clone(&shutdown);
     
     ctrlc::set_handler(move || {
-        println!("\n[{}] Ctrl+C received, shutting down...", timestamp());
+        status!("Ctrl+C received, shutting down...");
         shutdown_clone.store(true, Ordering::Relaxed);
     }).expect("Error setting Ctrl+C handler");
     
-    println!(
-        "[{}] Connecting Quote Client to {}:{} stream_addr: {} tickers: {}",
-        timestamp(),
-        cli.host, cli.port, cli.stream_addr, cli.tickers
+    status!(
+        "Connecting Quote Client to {}:{} stream_addr: {} tickers: {}",
+        cli.host,
+        cli.port,
+        match (cli.multicast, cli.transport) {
+            (true, _) => "multicast",
+            (false, Transport::Tcp) => "tcp",
+            (false, Transport::Udp) => cli.stream_addr.as_deref().unwrap_or("-"),
+        },
+        cli.tickers
     );
-    let (mut stream, mut reader) = connect(&cli.host, cli.port)?;
+    let mut control = ControlClient::connect(&cli.host, cli.port)?;
 
-    let command = &format!("STREAM udp://{} {}", cli.stream_addr, cli.tickers);
+    // Цены печатаются с точностью инструмента, которую знает только сервер
+    let prices = match control.specs(&cli.tickers) {
+        Ok(specs) => PriceFormat::new(specs),
+        Err(e) => {
+            status!("Price rules unavailable, printing two decimals: {}", e);
+            PriceFormat::default()
+        }
+    };
+
+    if cli.multicast {
+        stream_multicast(&cli, control, &prices, shutdown)
+    } else {
+        match (cli.transport, cli.stream_addr.as_deref()) {
+            (Transport::Tcp, _) => stream_tcp(&cli, control, &prices, shutdown),
+            (Transport::Udp, Some(stream_addr)) => stream_unicast(&cli, stream_addr, control, &prices, shutdown),
+            (Transport::Udp, None) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--stream_addr is required for the UDP transport",
+            )),
+        }
+    }
+}
+
+/// Creates the outputs selected on the command line
+fn sinks(cli: &cli_args::CliArgs, prices: &PriceFormat) -> Vec<Box<dyn QuoteSink>> {
+    let mut sinks: Vec<Box<dyn QuoteSink>> = Vec::new();
+    if cli.dashboard {
+        // Шаблоны вроде `A*` и `@TECH` раскрывает сервер, их строки появятся с первыми котировками
+        let tickers: Vec<String> = cli
+            .tickers
+            .split(',')
+            .map(|t| t.trim().to_uppercase())
+            .filter(|t| !t.contains('*') && !t.starts_with('@'))
+            .collect();
+        sinks.push(Box::new(Dashboard::start(&tickers, prices.clone())));
+    } else {
+        sinks.push(Box::new(StdoutSink::new(cli.format, prices.clone())));
+    }
+    if let Some(path) = cli.record.as_deref() {
+        let format = cli.record_format.unwrap_or_else(|| RecordFormat::from_path(path));
+        sinks.push(Box::new(QuoteRecorder::new(path, format, cli.record_max_bytes)));
+    }
+    sinks
+}
 
-    match send_command(&mut stream, &mut reader, command) {
+/// Asks the server for a UDP stream of our own and receives it until Ctrl+C
+fn stream_unicast(
+    cli: &cli_args::CliArgs,
+    stream_addr: &str,
+    mut control: ControlClient,
+    prices: &PriceFormat,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let command = &format!("STREAM udp://{} {}{}", stream_addr, cli.tickers, cli.stream_options());
+    // Сокет открывается до STREAM, иначе снимок последних котировок уходит в никуда
+    let quote_receiver = quote_udp_receiver::QuoteReceiver::new(stream_addr)?;
+
+    match control.send_command(command) {
         Ok(resp) => {
-            print!("[{}] Server response: {}", timestamp(), resp);
-            
+            status!("Server response: {}", resp.trim_end());
+
             // Extract server address from response
             let server_addr = resp
                 .split("server: ")
                 .nth(1)
                 .and_then(|s| s.split_whitespace().next())
-                .unwrap_or(&cli.stream_addr);
-            
-            let quote_receiver = quote_udp_receiver::QuoteReceiver::new(&cli.stream_addr)?;
-            if let Err(e) = quote_receiver.receive_loop(server_addr, shutdown) {
-                eprintln!("[{}] Receive loop failed: {}", timestamp(), e);
+                .unwrap_or(stream_addr);
+            let session = parse_session_id(&resp)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, resp.trim().to_string()))?;
+
+            let tracker = Arc::new(Mutex::new(SequenceTracker::new()));
+            let trackers = vec![(server_addr.to_string(), Arc::clone(&tracker))];
+            let stream = (Arc::new(Mutex::new(control)), session);
+            console::start(trackers.clone(), Some(stream.clone()));
+            if let Err(e) = quote_receiver.receive_loop(server_addr, shutdown, sinks(cli, prices), tracker, stream.clone()) {
+                status!("Receive loop failed: {}", e);
+            }
+            stop_session(&stream);
+            console::print_stats(&trackers);
+            status!("Client shutdown complete");
+        }
+        Err(e) => {
+            status!("Command failed: {}.", e);
+        }
+    }
+    Ok(())
+}
+
+/// Stops the stream session, so the server does not wait for it to time out
+fn stop_session((control, session): &StreamControl) {
+    if let Ok(mut control) = control.lock() {
+        match control.stop(*session) {
+            Ok(()) => status!("Stopped session {}", session),
+            Err(e) => status!("Failed to stop session {}: {}", session, e),
+        }
+    }
+}
+
+/// Asks the server for a TCP stream and receives it over a connection of its own until Ctrl+C
+fn stream_tcp(
+    cli: &cli_args::CliArgs,
+    mut control: ControlClient,
+    prices: &PriceFormat,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<()> {
+    match control.send_command(&format!("STREAM tcp:// {}{}", cli.tickers, cli.stream_options())) {
+        Ok(resp) => {
+            status!("Server response: {}", resp.trim_end());
+
+            // Порт берём из ответа, а адрес тот же, что у управляющего соединения: сервер может быть за NAT
+            let port = resp
+                .split("server: ")
+                .nth(1)
+                .and_then(|s| s.split_whitespace().next())
+                .and_then(|addr| addr.rsplit(':').next())
+                .and_then(|port| port.parse::<u16>().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, resp.trim().to_string()))?;
+            let session = parse_session_id(&resp)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, resp.trim().to_string()))?;
+
+            let quote_receiver = TcpQuoteReceiver::connect(&format!("{}:{}", cli.host, port))?;
+            let tracker = Arc::new(Mutex::new(SequenceTracker::new()));
+            let trackers = vec![(format!("{}:{}", cli.host, port), Arc::clone(&tracker))];
+            let stream = (Arc::new(Mutex::new(control)), session);
+            console::start(trackers.clone(), Some(stream.clone()));
+            if let Err(e) = quote_receiver.receive_loop(shutdown, sinks(cli, prices), tracker) {
+                status!("Receive loop failed: {}", e);
             }
-            println!("[{}] Client shutdown complete", timestamp());
+            stop_session(&stream);
+            console::print_stats(&trackers);
+            status!("Client shutdown complete");
         }
         Err(e) => {
-            eprintln!("[{}] Command failed: {}.", timestamp(), e);
+            status!("Command failed: {}.", e);
         }
     }
+    Ok(())
+}
 
+/// Joins the server multicast channels of the tickers and receives them until Ctrl+C
+fn stream_multicast(
+    cli: &cli_args::CliArgs,
+    mut control: ControlClient,
+    prices: &PriceFormat,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<()> {
+    // Мультикаст: подписка на общие каналы сервера
+    match control.send_command(&format!("MULTICAST {}", cli.tickers)) {
+        Ok(resp) => {
+            status!("Server response: {}", resp.trim_end());
+            let channels = parse_multicast_response(&resp)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let receiver = MulticastReceiver::join(&channels, cli.multicast_interface)?;
+            let trackers = receiver.trackers();
+            console::start(trackers.clone(), None);
+
+            // Каналы уже подключены: котировки новее снимка не потеряются
+            let mut sinks = sinks(cli, prices);
+            match control.snapshot(&cli.tickers) {
+                Ok(quotes) => {
+                    for quote in &quotes {
+                        deliver(&mut sinks, quote);
+                    }
+                }
+                Err(e) => status!("Snapshot failed: {}", e),
+            }
+            if let Err(e) = receiver.receive_loop(shutdown, sinks) {
+                status!("Receive loop failed: {}", e);
+            }
+            console::print_stats(&trackers);
+            status!("Client shutdown complete");
+        }
+        Err(e) => {
+            status!("Command failed: {}.", e);
+        }
+    }
     Ok(())
 }
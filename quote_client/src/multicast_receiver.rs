@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use quote_generator_lib::core::{SequencedQuote, StockQuote, WireMessage};
+
+use crate::console::NamedTracker;
+use crate::output::QuoteSink;
+use crate::quote_udp_receiver::deliver;
+use crate::sequence_tracker::{SeqEvent, SequenceTracker};
+
+const RECEIVE_BUFFER_SIZE: usize = 65536;
+const RECEIVE_TIMEOUT_MS: u64 = 200;
+
+/// Parses the `MULTICAST AAPL=239.255.0.1:7000 ...` answer into the channel of every ticker
+pub fn parse_multicast_response(response: &str) -> Result<Vec<(String, SocketAddrV4)>, String> {
+    let channels = response
+        .trim()
+        .strip_prefix("MULTICAST ")
+        .ok_or_else(|| response.trim().to_string())?;
+
+    channels
+        .split_whitespace()
+        .map(|channel| {
+            let (ticker, addr) = channel
+                .split_once('=')
+                .ok_or_else(|| format!("invalid multicast channel '{}'", channel))?;
+            let addr = addr
+                .parse()
+                .map_err(|_| format!("invalid multicast address '{}'", addr))?;
+            Ok((ticker.to_string(), addr))
+        })
+        .collect()
+}
+
+/// One joined multicast group and the subscribed tickers published on it
+struct Channel {
+    addr: SocketAddrV4,
+    tickers: HashSet<String>,
+    socket: UdpSocket,
+    tracker: Arc<Mutex<SequenceTracker>>,
+}
+
+/// Receives quotes from the multicast channels of the subscribed tickers
+///
+/// Every channel is a separate stream with its own sequence numbers; quotes of
+/// tickers that were not subscribed but share a channel are dropped
+pub struct MulticastReceiver {
+    channels: Vec<Channel>,
+}
+
+impl MulticastReceiver {
+    /// Joins the group of every channel on the given local interface
+    pub fn join(tickers: &[(String, SocketAddrV4)], interface: Ipv4Addr) -> io::Result<Self> {
+        let mut by_addr: BTreeMap<SocketAddrV4, HashSet<String>> = BTreeMap::new();
+        for (ticker, addr) in tickers {
+            by_addr.entry(*addr).or_default().insert(ticker.clone());
+        }
+
+        let mut channels = Vec::with_capacity(by_addr.len());
+        for (addr, tickers) in by_addr {
+            let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+            // Несколько клиентов на одной машине слушают один и тот же порт
+            socket.set_reuse_address(true)?;
+            socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, addr.port())).into())?;
+            socket.join_multicast_v4(addr.ip(), &interface)?;
+            socket.set_read_timeout(Some(Duration::from_millis(RECEIVE_TIMEOUT_MS)))?;
+
+            status!(
+                "Joined multicast group {} for {}",
+                addr,
+                tickers.iter().cloned().collect::<Vec<_>>().join(",")
+            );
+            channels.push(Channel {
+                addr,
+                tickers,
+                socket: socket.into(),
+                tracker: Arc::new(Mutex::new(SequenceTracker::joined_mid_stream())),
+            });
+        }
+        Ok(Self { channels })
+    }
+
+    /// Returns the sequence tracker of every joined channel
+    pub fn trackers(&self) -> Vec<NamedTracker> {
+        self.channels
+            .iter()
+            .map(|c| (c.addr.to_string(), Arc::clone(&c.tracker)))
+            .collect()
+    }
+
+    /// Receives quotes from all channels and hands them to the sinks until Ctrl+C or no sink is left
+    ///
+    /// Each channel is read on its own thread. Lost quotes are reported but cannot be
+    /// recovered, the server keeps no history for multicast channels
+    pub fn receive_loop(
+        self,
+        shutdown: Arc<AtomicBool>,
+        mut sinks: Vec<Box<dyn QuoteSink>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (sender, receiver) = mpsc::channel::<StockQuote>();
+        let running = Arc::new(AtomicBool::new(true));
+
+        for channel in self.channels {
+            let sender = sender.clone();
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                let mut buf = vec![0u8; RECEIVE_BUFFER_SIZE];
+                while running.load(Ordering::Relaxed) {
+                    let size = match channel.socket.recv(&mut buf) {
+                        Ok(size) => size,
+                        Err(e) if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+                        ) => continue,
+                        Err(e) => {
+                            status!("Failed to receive from {}: {}", channel.addr, e);
+                            break;
+                        }
+                    };
+
+                    let quotes = match WireMessage::decode(&buf[..size]) {
+                        Ok(WireMessage::Quote(quote)) => vec![quote],
+                        Ok(WireMessage::Batch(quotes)) => quotes,
+                        Ok(_) => continue,
+                        Err(e) => {
+                            status!("Ignoring invalid datagram on {}: {}", channel.addr, e);
+                            continue;
+                        }
+                    };
+
+                    for SequencedQuote { seq, quote } in quotes {
+                        let event = match channel.tracker.lock() {
+                            Ok(mut tracker) => tracker.on_seq(seq),
+                            Err(_) => SeqEvent::InOrder,
+                        };
+                        match event {
+                            SeqEvent::Gap { from, to } => status!(
+                                "Gap on {}: {} quote(s) missing, seq {}..={}",
+                                channel.addr,
+                                to - from + 1,
+                                from,
+                                to
+                            ),
+                            SeqEvent::Duplicate => continue,
+                            SeqEvent::InOrder | SeqEvent::Reordered => {}
+                        }
+                        if channel.tickers.contains(&quote.ticker) && sender.send(quote).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        status!("Waiting for quotes...");
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                status!("Shutdown signal received, stopping client");
+                break;
+            }
+
+            match receiver.recv_timeout(Duration::from_millis(RECEIVE_TIMEOUT_MS)) {
+                Ok(quote) => {
+                    deliver(&mut sinks, &quote);
+                    if sinks.is_empty() {
+                        status!("No output left, stopping client");
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    status!("All multicast channels failed, shutting down client");
+                    break;
+                }
+            }
+        }
+        running.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
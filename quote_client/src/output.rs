@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use chrono::{Local, TimeZone};
+use clap::ValueEnum;
+
+use quote_generator_lib::core::{InstrumentSpec, Price, StockQuote};
+
+use crate::quote_recorder::QuoteRecorder;
+
+/// Number of table rows between two repeated headers
+const TABLE_HEADER_EVERY: usize = 20;
+const CSV_HEADER: &str = "ticker,price,volume,timestamp,bid,ask,bid_size,ask_size,last_size";
+
+/// Format received quotes are printed in on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per line
+    Json,
+    /// Comma-separated values with a header line
+    Csv,
+    /// Pipe-delimited `StockQuote` text format, as used by recordings and replay
+    Pipe,
+    /// Aligned columns for humans
+    Table,
+}
+
+/// Consumer of received quotes, e.g. stdout or a recording
+pub trait QuoteSink {
+    /// Handles one received quote, an error stops this sink
+    fn on_quote(&mut self, quote: &StockQuote) -> io::Result<()>;
+
+    /// Handles a quote delivered after newer quotes of the stream, e.g. one recovered by `RESEND`
+    ///
+    /// Handled like any other quote unless the sink keeps the latest state of a ticker
+    fn on_late_quote(&mut self, quote: &StockQuote) -> io::Result<()> {
+        self.on_quote(quote)
+    }
+
+    /// Short name used in status messages
+    fn name(&self) -> &str;
+}
+
+/// Decimal places the prices of each ticker are printed with, taken from the price rules reported by the server
+///
+/// Every price of a ticker gets the same number of decimals, tickers without rules are printed like US equities
+#[derive(Debug, Clone, Default)]
+pub struct PriceFormat {
+    specs: HashMap<String, InstrumentSpec>,
+}
+
+impl PriceFormat {
+    /// Creates a new PriceFormat from the price rules of the tickers
+    pub fn new(specs: HashMap<String, InstrumentSpec>) -> Self {
+        Self { specs }
+    }
+
+    /// Formats a price of the ticker with the ticker precision
+    pub fn format(&self, ticker: &str, price: Price) -> String {
+        self.specs.get(ticker).copied().unwrap_or_default().format_price(price)
+    }
+}
+
+/// Turns quotes into lines of the selected output format
+pub struct QuoteFormatter {
+    format: OutputFormat,
+    prices: PriceFormat,
+    rows: usize,
+}
+
+impl QuoteFormatter {
+    /// Creates a new QuoteFormatter
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            prices: PriceFormat::default(),
+            rows: 0,
+        }
+    }
+
+    /// Sets the precision table prices are printed with
+    pub fn with_prices(mut self, prices: PriceFormat) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Returns the lines to print for the quote, headers included when due
+    pub fn format(&mut self, quote: &StockQuote) -> Vec<String> {
+        let mut lines = Vec::with_capacity(2);
+
+        match self.format {
+            OutputFormat::Json => lines.push(serde_json::to_string(quote).unwrap_or_default()),
+            OutputFormat::Csv => {
+                if self.rows == 0 {
+                    lines.push(CSV_HEADER.to_string());
+                }
+                lines.push(quote.to_string().replace('|', ","));
+            }
+            OutputFormat::Pipe => lines.push(quote.to_string()),
+            OutputFormat::Table => {
+                if self.rows.is_multiple_of(TABLE_HEADER_EVERY) {
+                    lines.push(format!(
+                        "{:<12} {:<8} {:>12} {:>12} {:>12} {:>9} {:>9} {:>9} {:>12}",
+                        "TIME", "TICKER", "LAST", "BID", "ASK", "BID SIZE", "ASK SIZE", "LAST SIZE", "VOLUME"
+                    ));
+                }
+                lines.push(format!(
+                    "{:<12} {:<8} {:>12} {:>12} {:>12} {:>9} {:>9} {:>9} {:>12}",
+                    format_time(quote.timestamp),
+                    quote.ticker,
+                    self.prices.format(&quote.ticker, quote.price),
+                    self.prices.format(&quote.ticker, quote.bid),
+                    self.prices.format(&quote.ticker, quote.ask),
+                    quote.bid_size,
+                    quote.ask_size,
+                    quote.last_size,
+                    quote.volume
+                ));
+            }
+        }
+
+        self.rows += 1;
+        lines
+    }
+}
+
+/// Formats a millisecond UNIX timestamp as local `HH:MM:SS.mmm`
+pub fn format_time(timestamp_ms: u64) -> String {
+    match Local.timestamp_millis_opt(timestamp_ms as i64).single() {
+        Some(time) => time.format("%H:%M:%S%.3f").to_string(),
+        None => timestamp_ms.to_string(),
+    }
+}
+
+/// Prints quotes on stdout in the selected format
+pub struct StdoutSink {
+    formatter: QuoteFormatter,
+}
+
+impl StdoutSink {
+    /// Creates a new StdoutSink printing table prices with the given precision
+    pub fn new(format: OutputFormat, prices: PriceFormat) -> Self {
+        Self {
+            formatter: QuoteFormatter::new(format).with_prices(prices),
+        }
+    }
+}
+
+impl QuoteSink for StdoutSink {
+    fn on_quote(&mut self, quote: &StockQuote) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        for line in self.formatter.format(quote) {
+            writeln!(stdout, "{}", line)?;
+        }
+        stdout.flush()
+    }
+
+    fn name(&self) -> &str {
+        "stdout"
+    }
+}
+
+impl QuoteSink for QuoteRecorder {
+    fn on_quote(&mut self, quote: &StockQuote) -> io::Result<()> {
+        self.record(quote)
+    }
+
+    fn name(&self) -> &str {
+        "recording"
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use quote_generator_lib::core::{RecordFormat, StockQuote, write_quote};
+
+/// Header written at the top of every text file, skipped when the file is replayed
+const TEXT_HEADER: &str = "# ticker|price|volume|timestamp|bid|ask|bid_size|ask_size|last_size";
+
+/// Persists received quotes to disk, rotating files daily and optionally by size
+///
+/// For a base path `quotes.csv` the files are named `quotes-2026-01-14.csv`,
+/// then `quotes-2026-01-14.1.csv`, `quotes-2026-01-14.2.csv`... once a file exceeds the size limit.
+/// An extension that does not match the format is replaced, e.g. `.bin` for binary files.
+pub struct QuoteRecorder {
+    base: PathBuf,
+    format: RecordFormat,
+    max_bytes: Option<u64>,
+    writer: Option<BufWriter<File>>,
+    date: String,
+    part: u32,
+    written: u64,
+}
+
+impl QuoteRecorder {
+    /// Creates a new QuoteRecorder, files are opened lazily on the first quote
+    pub fn new(base: &Path, format: RecordFormat, max_bytes: Option<u64>) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            format,
+            max_bytes,
+            writer: None,
+            date: String::new(),
+            part: 0,
+            written: 0,
+        }
+    }
+
+    /// Returns the file quotes of the given day and part go to
+    pub fn file_path(&self, date: &str, part: u32) -> PathBuf {
+        let stem = self
+            .base
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "quotes".to_string());
+        // Расширение должно совпадать с форматом, иначе воспроизведение прочитает файл не так
+        let extension = match self.base.extension() {
+            Some(e) if RecordFormat::from_path(&self.base) == self.format => e.to_string_lossy().into_owned(),
+            _ => self.format.extension().to_string(),
+        };
+
+        let name = if part == 0 {
+            format!("{}-{}.{}", stem, date, extension)
+        } else {
+            format!("{}-{}.{}.{}", stem, date, part, extension)
+        };
+        self.base.with_file_name(name)
+    }
+
+    /// Records a quote into the file of the current local day
+    pub fn record(&mut self, quote: &StockQuote) -> io::Result<()> {
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        self.record_on(&date, quote)
+    }
+
+    /// Records a quote into the file of the given day
+    pub fn record_on(&mut self, date: &str, quote: &StockQuote) -> io::Result<()> {
+        let mut encoded = Vec::new();
+        write_quote(&mut encoded, self.format, quote)?;
+
+        if self.writer.is_none() || self.date != date {
+            self.open(date, 0)?;
+        } else if let Some(max_bytes) = self.max_bytes
+            && self.written > 0
+            && self.written + encoded.len() as u64 > max_bytes
+        {
+            self.open(date, self.part + 1)?;
+        }
+
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(&encoded)?;
+            self.written += encoded.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Flushes buffered quotes to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Opens the first file of the day starting at `part` that still has room, appending to it
+    fn open(&mut self, date: &str, mut part: u32) -> io::Result<()> {
+        self.flush()?;
+
+        loop {
+            let path = self.file_path(date, part);
+            let existing = path.metadata().map(|m| m.len()).unwrap_or(0);
+            if let Some(max_bytes) = self.max_bytes
+                && existing >= max_bytes
+            {
+                part += 1;
+                continue;
+            }
+
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let mut writer = BufWriter::new(file);
+            let mut written = existing;
+            if existing == 0 && self.format == RecordFormat::Text {
+                writeln!(writer, "{}", TEXT_HEADER)?;
+                written += TEXT_HEADER.len() as u64 + 1;
+            }
+
+            status!("Recording quotes to {}", path.display());
+            self.writer = Some(writer);
+            self.date = date.to_string();
+            self.part = part;
+            self.written = written;
+            return Ok(());
+        }
+    }
+}
+
+impl Drop for QuoteRecorder {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
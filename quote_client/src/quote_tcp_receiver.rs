@@ -0,0 +1,109 @@
+use std::io::{self, BufReader};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use quote_generator_lib::core::{SequencedQuote, WireMessage};
+
+use crate::output::QuoteSink;
+use crate::quote_udp_receiver::deliver;
+use crate::sequence_tracker::{SeqEvent, SequenceTracker};
+
+const RECEIVE_TIMEOUT_MS: u64 = 200;
+
+/// TCP receiver for clients that cannot receive UDP, e.g. behind NAT
+///
+/// Reads the wire frames the server writes one after another on a connection of its own
+pub struct TcpQuoteReceiver {
+    stream: TcpStream,
+}
+
+impl TcpQuoteReceiver {
+    /// Connects to the stream address announced by the server
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        status!("Connected to TCP stream {}", addr);
+        Ok(Self { stream })
+    }
+
+    /// Receives quotes and hands them to the sinks until Ctrl+C, the server closing the stream or no sink is left
+    ///
+    /// Frames are read on a thread of their own. TCP keeps quotes in order, `tracker` still counts them.
+    pub fn receive_loop(
+        self,
+        shutdown: Arc<AtomicBool>,
+        mut sinks: Vec<Box<dyn QuoteSink>>,
+        tracker: Arc<Mutex<SequenceTracker>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (sender, receiver) = mpsc::channel::<io::Result<Vec<SequencedQuote>>>();
+        let mut reader = BufReader::new(self.stream.try_clone()?);
+        thread::spawn(move || {
+            loop {
+                let quotes = match WireMessage::read_from(&mut reader) {
+                    Ok(Some(WireMessage::Quote(quote))) => vec![quote],
+                    Ok(Some(WireMessage::Batch(quotes))) => quotes,
+                    Ok(Some(_)) => continue,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                };
+                if sender.send(Ok(quotes)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        status!("Waiting for quotes...");
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                status!("Shutdown signal received, stopping client");
+                break;
+            }
+
+            match receiver.recv_timeout(Duration::from_millis(RECEIVE_TIMEOUT_MS)) {
+                Ok(Ok(quotes)) => {
+                    for SequencedQuote { seq, quote } in quotes {
+                        let event = match tracker.lock() {
+                            Ok(mut tracker) => tracker.on_seq(seq),
+                            Err(_) => SeqEvent::InOrder,
+                        };
+                        match event {
+                            SeqEvent::Gap { from, to } => status!(
+                                "Gap in quote stream: {} quote(s) missing, seq {}..={}",
+                                to - from + 1,
+                                from,
+                                to
+                            ),
+                            SeqEvent::Duplicate => continue,
+                            SeqEvent::InOrder | SeqEvent::Reordered => {}
+                        }
+                        deliver(&mut sinks, &quote);
+                    }
+                    if sinks.is_empty() {
+                        status!("No output left, stopping client");
+                        break;
+                    }
+                }
+                Ok(Err(e)) => {
+                    status!("Failed to read from TCP stream: {}", e);
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    status!("Server closed the TCP stream, shutting down client");
+                    break;
+                }
+            }
+        }
+
+        // Разблокирует поток чтения
+        let _ = self.stream.shutdown(Shutdown::Both);
+        Ok(())
+    }
+}
@@ -2,14 +2,21 @@
 use std::net::UdpSocket;
 use std::thread;
 use std::time::Duration;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, mpsc};
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use quote_generator_lib::core::StockQuote;
-use quote_generator_lib::timestamp;
+use quote_generator_lib::core::{SequencedQuote, StockQuote, WireMessage};
+
+use crate::control::StreamControl;
+use crate::output::QuoteSink;
+use crate::sequence_tracker::{SeqEvent, SequenceTracker};
 
 const PING_INTERVAL_SECS: u64 = 2;
-const RECEIVE_BUFFER_SIZE: usize = 1024;
+/// Large enough for any UDP datagram, batches included
+const RECEIVE_BUFFER_SIZE: usize = 65536;
+const RECEIVE_TIMEOUT_MS: u64 = 200;
+/// Largest gap asked for again at once, matching the server limit
+const MAX_RESEND_QUOTES: u64 = 1000;
 
 /// UDP receiver for receiving stock quotes from the server
 pub struct QuoteReceiver {
@@ -20,31 +27,47 @@ impl QuoteReceiver {
     /// Creates a new QuoteReceiver bound to the specified address
     pub fn new(bind_addr: &str) -> Result<Self, std::io::Error> {
         let socket = UdpSocket::bind(bind_addr)?;
-        println!("[{}] Ресивер запущен на {}", timestamp(), bind_addr);
+        status!("Receiver started on {}", bind_addr);
         Ok(Self { socket })
     }
 
     /// Starts the receive loop, connecting to server and handling quotes and ping/pong
     /// 
-    /// Sends ping every 2 seconds and receives quotes from the server
-    pub fn receive_loop(self, server_addr: &str, shutdown: Arc<AtomicBool>) -> Result<(), Box<dyn std::error::Error>> {
+    /// Sends ping every 2 seconds and receives quotes from the server, single or batched, handing each one to all sinks.
+    /// A failing sink is dropped, the loop stops once no sink is left.
+    /// Sequence numbers are checked by `tracker`, duplicated quotes are not handed to the sinks
+    /// and gaps are filled by asking the server to resend the missing quotes of the `stream` session.
+    /// The resend round trip runs on a thread of its own, so quotes keep being received meanwhile.
+    /// Recovered quotes are not re-sequenced: they are handed to the sinks as late quotes once they arrive,
+    /// after newer quotes of the stream
+    pub fn receive_loop(
+        self,
+        server_addr: &str,
+        shutdown: Arc<AtomicBool>,
+        mut sinks: Vec<Box<dyn QuoteSink>>,
+        tracker: Arc<Mutex<SequenceTracker>>,
+        stream: StreamControl,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         // Connect socket to server for bidirectional UDP communication
         self.socket.connect(server_addr)?;
+        // Read timeout lets the loop notice Ctrl+C even when the server sends nothing
+        self.socket.set_read_timeout(Some(Duration::from_millis(RECEIVE_TIMEOUT_MS)))?;
         
-        let mut buf = [0u8; RECEIVE_BUFFER_SIZE];
+        let mut buf = vec![0u8; RECEIVE_BUFFER_SIZE];
         // Shared flag to coordinate shutdown between threads
         let running = Arc::new(AtomicBool::new(true));
         
-        // Thread 1: Ping sender - sends "ping" message to server every 2 seconds
+        // Thread 1: Ping sender - sends a ping frame to server every 2 seconds
         // This keeps the connection alive and lets server know client is still active
         let socket_clone = self.socket.try_clone()?;
         let running_clone = Arc::clone(&running);
         let shutdown_clone = Arc::clone(&shutdown);
+        let ping = WireMessage::Ping.encode()?;
         thread::spawn(move || {
             while running_clone.load(Ordering::Relaxed) && !shutdown_clone.load(Ordering::Relaxed) {
                 // Send ping message to server
-                if let Err(e) = socket_clone.send(b"ping") {
-                    eprintln!("[{}] Failed to send ping: {}", timestamp(), e);
+                if let Err(e) = socket_clone.send(&ping) {
+                    status!("Failed to send ping: {}", e);
                     break;
                 }
                 // Wait 2 seconds before sending next ping
@@ -52,41 +75,86 @@ impl QuoteReceiver {
             }
         });
 
-        println!("[{}] Ожидание данных...", timestamp());
+        // Thread 2: Gap recovery - asks the server to resend the missing quotes of every gap reported by the loop
+        let (gap_tx, gap_rx) = mpsc::channel::<(u64, u64)>();
+        let (recovered_tx, recovered_rx) = mpsc::channel::<Vec<StockQuote>>();
+        let tracker_clone = Arc::clone(&tracker);
+        thread::spawn(move || {
+            // Поток завершается, когда цикл приёма закрывает канал пропусков
+            for (from, to) in gap_rx {
+                let recovered = recover(&stream, &tracker_clone, from, to);
+                if recovered_tx.send(recovered).is_err() {
+                    break;
+                }
+            }
+        });
+
+        status!("Waiting for quotes...");
 
         // Main receive loop - receives both pong responses and stock quotes from server
         loop {
             // Check for Ctrl+C signal
             if shutdown.load(Ordering::Relaxed) {
-                println!("[{}] Shutdown signal received, stopping client", timestamp());
+                status!("Shutdown signal received, stopping client");
                 running.store(false, Ordering::Relaxed);
                 break;
             }
+
+            // Восстановленные котировки отдаются, как только поток восстановления их получил, уже после более новых
+            for quote in recovered_rx.try_iter().flatten() {
+                deliver_late(&mut sinks, &quote);
+            }
             
             match self.socket.recv(&mut buf) {
                 Ok(size) => {
-                    // Check if received message is a "pong" response
-                    if let Ok(msg) = std::str::from_utf8(&buf[..size]) 
-                        && msg.trim() == "pong" {
-                            // Ignore pong messages, they're just keep-alive responses
+                    let quotes = match WireMessage::decode(&buf[..size]) {
+                        Ok(WireMessage::Quote(quote)) => vec![quote],
+                        Ok(WireMessage::Batch(quotes)) => quotes,
+                        // Pong messages are just keep-alive responses
+                        Ok(WireMessage::Pong) | Ok(WireMessage::Ping) => continue,
+                        Err(e) => {
+                            status!("Ignoring invalid datagram: {}", e);
                             continue;
-                        
-                    }
-                    // Try to deserialize as StockQuote
-                    match bincode::deserialize::<StockQuote>(&buf[..size]) {
-                        Ok(quote) => {
-                            // Successfully received and deserialized a quote
-                            println!("[{}] {:?}", timestamp(), quote);
                         }
-                        Err(_) => {
-                            // Silently ignore deserialization errors (could be pong or corrupted data)
+                    };
+
+                    for SequencedQuote { seq, quote } in quotes {
+                        let event = match tracker.lock() {
+                            Ok(mut tracker) => tracker.on_seq(seq),
+                            Err(_) => SeqEvent::InOrder,
+                        };
+                        match event {
+                            SeqEvent::Gap { from, to } => {
+                                status!(
+                                    "Gap in quote stream: {} quote(s) missing, seq {}..={}",
+                                    to - from + 1,
+                                    from,
+                                    to
+                                );
+                                let _ = gap_tx.send((from, to));
+                            }
+                            SeqEvent::Duplicate => continue,
+                            SeqEvent::InOrder | SeqEvent::Reordered => {}
                         }
+                        deliver(&mut sinks, &quote);
+                    }
+                    if sinks.is_empty() {
+                        status!("No output left, stopping client");
+                        running.store(false, Ordering::Relaxed);
+                        break;
                     }
                 }
+                Err(e) if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut | std::io::ErrorKind::Interrupted
+                ) => {
+                    // Nothing received within the timeout or interrupted by Ctrl+C, check the shutdown flag again
+                    continue;
+                }
                 Err(e) => {
                     // Connection error - server likely disconnected
-                    eprintln!("[{}] Ошибка получения данных: {}", timestamp(), e);
-                    println!("[{}] Server disconnected, shutting down client", timestamp());
+                    status!("Failed to receive data: {}", e);
+                    status!("Server disconnected, shutting down client");
                     // Signal ping sender thread to stop
                     running.store(false, Ordering::Relaxed);
                     break;
@@ -96,3 +164,64 @@ impl QuoteReceiver {
         Ok(())
     }
 }
+
+/// Hands a quote to every sink, dropping the ones that fail
+pub fn deliver(sinks: &mut Vec<Box<dyn QuoteSink>>, quote: &StockQuote) {
+    sinks.retain_mut(|sink| match sink.on_quote(quote) {
+        Ok(()) => true,
+        Err(e) => {
+            status!("Failed to write quote to {}, output stopped: {}", sink.name(), e);
+            false
+        }
+    });
+}
+
+/// Hands a quote older than the ones already delivered to every sink, dropping the ones that fail
+fn deliver_late(sinks: &mut Vec<Box<dyn QuoteSink>>, quote: &StockQuote) {
+    sinks.retain_mut(|sink| match sink.on_late_quote(quote) {
+        Ok(()) => true,
+        Err(e) => {
+            status!("Failed to write quote to {}, output stopped: {}", sink.name(), e);
+            false
+        }
+    });
+}
+
+/// Asks the server for the quotes of a gap, returns those still missing in sequence order
+fn recover(
+    (control, session): &StreamControl,
+    tracker: &Mutex<SequenceTracker>,
+    from: u64,
+    to: u64,
+) -> Vec<StockQuote> {
+    let from = from.max(to.saturating_sub(MAX_RESEND_QUOTES - 1));
+    let resent = match control.lock() {
+        Ok(mut control) => control.resend(*session, from, to),
+        Err(_) => return Vec::new(),
+    };
+
+    match resent {
+        Ok(quotes) => {
+            let Ok(mut tracker) = tracker.lock() else {
+                return Vec::new();
+            };
+            let recovered: Vec<StockQuote> = quotes
+                .into_iter()
+                .filter(|q| tracker.on_recovered(q.seq))
+                .map(|q| q.quote)
+                .collect();
+            status!(
+                "Recovered {} of {} missing quote(s), seq {}..={}, delivering them after newer quotes",
+                recovered.len(),
+                to - from + 1,
+                from,
+                to
+            );
+            recovered
+        }
+        Err(e) => {
+            status!("Failed to recover seq {}..={}: {}", from, to, e);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Largest number of missing sequence numbers remembered, older ones are counted as lost
+const MAX_TRACKED_MISSING: usize = 10_000;
+
+/// What a received sequence number means for the stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqEvent {
+    /// The next expected quote
+    InOrder,
+    /// Quotes `from..=to` were skipped, they may still arrive late
+    Gap { from: u64, to: u64 },
+    /// A quote that was missing arrived late
+    Reordered,
+    /// A quote that was already received
+    Duplicate,
+}
+
+/// Detects lost, duplicated and reordered quotes of one streaming session
+///
+/// Received quotes are counted in `received`, retransmitted ones in `recovered`
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    next_expected: u64,
+    missing: BTreeSet<u64>,
+    received: u64,
+    duplicates: u64,
+    reordered: u64,
+    recovered: u64,
+    gaps: u64,
+    forgotten: u64,
+}
+
+impl SequenceTracker {
+    /// Creates a new SequenceTracker expecting sequence number 1
+    pub fn new() -> Self {
+        Self {
+            next_expected: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new SequenceTracker for a stream joined while running, e.g. a multicast channel
+    ///
+    /// The first sequence number received is taken as the start of the stream
+    pub fn joined_mid_stream() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for a received sequence number
+    pub fn on_seq(&mut self, seq: u64) -> SeqEvent {
+        self.received += 1;
+
+        // Номер 0 не используется: ждём первый номер потока
+        if self.next_expected == 0 {
+            self.next_expected = seq.saturating_add(1);
+            return SeqEvent::InOrder;
+        }
+
+        if seq == self.next_expected {
+            self.next_expected = seq.saturating_add(1);
+            return SeqEvent::InOrder;
+        }
+
+        if seq > self.next_expected {
+            let from = self.next_expected;
+            let to = seq - 1;
+            // Огромный пропуск не перебираем: запоминаем только последние номера, остальные сразу потеряны
+            let tracked_from = from.max(to.saturating_sub(MAX_TRACKED_MISSING as u64 - 1));
+            self.forgotten += tracked_from - from;
+            self.missing.extend(tracked_from..=to);
+            self.gaps += 1;
+            self.next_expected = seq.saturating_add(1);
+
+            // Не храним бесконечно много пропусков, самые старые считаем потерянными
+            while self.missing.len() > MAX_TRACKED_MISSING {
+                self.missing.pop_first();
+                self.forgotten += 1;
+            }
+            return SeqEvent::Gap { from, to };
+        }
+
+        if self.missing.remove(&seq) {
+            self.reordered += 1;
+            SeqEvent::Reordered
+        } else {
+            self.duplicates += 1;
+            SeqEvent::Duplicate
+        }
+    }
+
+    /// Accounts for a quote retransmitted on request, returns false if it is no longer missing
+    pub fn on_recovered(&mut self, seq: u64) -> bool {
+        let missing = self.missing.remove(&seq);
+        if missing {
+            self.recovered += 1;
+        }
+        missing
+    }
+}
+
+impl fmt::Display for SequenceTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "received {}, last seq {}, missing {} in {} gaps, recovered {}, reordered {}, duplicates {}",
+            self.received,
+            self.next_expected.saturating_sub(1),
+            self.missing.len() as u64 + self.forgotten,
+            self.gaps,
+            self.recovered,
+            self.reordered,
+            self.duplicates
+        )
+    }
+}
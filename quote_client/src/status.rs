@@ -0,0 +1,43 @@
+use std::sync::Mutex;
+
+use quote_generator_lib::timestamp;
+
+/// Last status message while the dashboard owns the terminal, `None` while messages go to stderr
+static DASHBOARD_STATUS: Mutex<Option<String>> = Mutex::new(None);
+
+/// Prints a timestamped status message on stderr, or shows it on the dashboard status line while the dashboard is on screen
+pub fn report(message: &str) {
+    let line = format!("[{}] {}", timestamp(), message);
+    if let Ok(mut status) = DASHBOARD_STATUS.lock()
+        && let Some(status) = status.as_mut()
+    {
+        *status = line;
+        return;
+    }
+    eprintln!("{}", line);
+}
+
+/// Shows status messages on the dashboard status line until `release`
+pub fn capture() {
+    if let Ok(mut status) = DASHBOARD_STATUS.lock() {
+        *status = Some(String::new());
+    }
+}
+
+/// Returns the dashboard status line, empty when there is nothing to show
+pub fn current() -> String {
+    DASHBOARD_STATUS
+        .lock()
+        .ok()
+        .and_then(|status| status.clone())
+        .unwrap_or_default()
+}
+
+/// Sends status messages to stderr again and returns the last one the dashboard showed
+pub fn release() -> Option<String> {
+    DASHBOARD_STATUS
+        .lock()
+        .ok()
+        .and_then(|mut status| status.take())
+        .filter(|line| !line.is_empty())
+}
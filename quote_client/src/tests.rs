@@ -1,31 +1,369 @@
 #[cfg(test)]
-mod tests {
-    use quote_generator_lib::core::StockQuote;
+mod unit {
+    use clap::Parser;
+    use quote_generator_lib::core::{QuoteReader, RecordFormat, SequencedQuote, StockQuote, WireMessage};
+
+    use crate::cli_args::{CliArgs, Transport};
+    use crate::control::{
+        parse_resend_header, parse_session_id, parse_snapshot_header, parse_spec_line, parse_tickers_reply,
+    };
+    use crate::dashboard::DashboardState;
+    use crate::multicast_receiver::parse_multicast_response;
+    use crate::output::{OutputFormat, PriceFormat, QuoteFormatter};
+    use crate::quote_recorder::QuoteRecorder;
+    use crate::sequence_tracker::{SeqEvent, SequenceTracker};
 
     #[test]
     fn deserialize_quote() {
-        let quote = StockQuote::new("GOOGL", 150.0, 2000, 9876543210);
+        let quote = StockQuote::new("GOOGL", 150.0, 2000, 9876543210).with_book(149.95, 150.0, 500, 700);
         let serialized = bincode::serialize(&quote).unwrap();
         let deserialized: StockQuote = bincode::deserialize(&serialized).unwrap();
         
         assert_eq!(deserialized.ticker, "GOOGL");
         assert_eq!(deserialized.price, 150.0);
+        assert_eq!(deserialized.bid, 149.95);
+        assert_eq!(deserialized.ask, 150.0);
     }
 
     #[test]
     fn pong_message_detection() {
-        let pong_msg = b"pong";
-        let result = std::str::from_utf8(pong_msg);
-        
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().trim(), "pong");
+        let pong = WireMessage::Pong.encode().unwrap();
+
+        assert_eq!(WireMessage::decode(&pong).unwrap(), WireMessage::Pong);
     }
 
     #[test]
     fn invalid_message_handling() {
-        let invalid = b"invalid";
-        let result = bincode::deserialize::<StockQuote>(invalid);
-        
-        assert!(result.is_err());
+        assert!(WireMessage::decode(b"invalid").is_err());
+        assert!(WireMessage::decode(b"pong").is_err());
+
+        // A headerless quote from an old streamer is rejected instead of misparsed
+        let bare = bincode::serialize(&SequencedQuote::new(1, StockQuote::new("AAPL", 183.15, 100, 1))).unwrap();
+        assert!(WireMessage::decode(&bare).is_err());
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("quote_client_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_back(path: &std::path::Path) -> Vec<StockQuote> {
+        QuoteReader::open(path).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn recorder_rotates_daily() {
+        let dir = temp_dir("daily");
+        let mut recorder = QuoteRecorder::new(&dir.join("quotes.csv"), RecordFormat::Text, None);
+        let first = StockQuote::new("AAPL", 183.15, 100, 1);
+        let second = StockQuote::new("MSFT", 410.5, 200, 2);
+
+        recorder.record_on("2026-01-14", &first).unwrap();
+        recorder.record_on("2026-01-15", &second).unwrap();
+        recorder.flush().unwrap();
+
+        assert_eq!(read_back(&dir.join("quotes-2026-01-14.csv")), vec![first]);
+        assert_eq!(read_back(&dir.join("quotes-2026-01-15.csv")), vec![second]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recorder_rotates_by_size() {
+        let dir = temp_dir("size");
+        let quote = StockQuote::new("AAPL", 183.15, 100, 1);
+        let mut recorder = QuoteRecorder::new(&dir.join("quotes.bin"), RecordFormat::Binary, Some(150));
+
+        for _ in 0..5 {
+            recorder.record_on("2026-01-14", &quote).unwrap();
+        }
+        drop(recorder);
+
+        let parts = [
+            dir.join("quotes-2026-01-14.bin"),
+            dir.join("quotes-2026-01-14.1.bin"),
+            dir.join("quotes-2026-01-14.2.bin"),
+        ];
+        let total: usize = parts.iter().map(|p| read_back(p).len()).sum();
+        assert_eq!(total, 5);
+        assert!(parts.iter().all(|p| std::fs::metadata(p).unwrap().len() <= 150));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recorder_extension_follows_format() {
+        let dir = temp_dir("extension");
+        let quote = StockQuote::new("AAPL", 183.15, 100, 1);
+        let mut recorder = QuoteRecorder::new(&dir.join("quotes.csv"), RecordFormat::Binary, None);
+        assert_eq!(recorder.file_path("2026-01-14", 1), dir.join("quotes-2026-01-14.1.bin"));
+        recorder.record_on("2026-01-14", &quote).unwrap();
+        drop(recorder);
+
+        // Воспроизведение определяет формат по расширению
+        assert_eq!(read_back(&dir.join("quotes-2026-01-14.bin")), vec![quote]);
+        let text = QuoteRecorder::new(&dir.join("quotes.txt"), RecordFormat::Text, None);
+        assert_eq!(text.file_path("2026-01-14", 0), dir.join("quotes-2026-01-14.txt"));
+        let text = QuoteRecorder::new(&dir.join("quotes.bin"), RecordFormat::Text, None);
+        assert_eq!(text.file_path("2026-01-14", 0), dir.join("quotes-2026-01-14.csv"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn sample_quote() -> StockQuote {
+        StockQuote::new("AAPL", 183.15, 3980, 1768380781617)
+            .with_book(183.12, 183.15, 2400, 1300)
+            .with_last_size(57)
+    }
+
+    #[test]
+    fn format_pipe_and_csv() {
+        let mut pipe = QuoteFormatter::new(OutputFormat::Pipe);
+        assert_eq!(pipe.format(&sample_quote()), ["AAPL|183.15|3980|1768380781617|183.12|183.15|2400|1300|57"]);
+
+        let mut csv = QuoteFormatter::new(OutputFormat::Csv);
+        let first = csv.format(&sample_quote());
+        assert_eq!(first.len(), 2);
+        assert!(first[0].starts_with("ticker,price,"));
+        assert_eq!(first[1], "AAPL,183.15,3980,1768380781617,183.12,183.15,2400,1300,57");
+        assert_eq!(csv.format(&sample_quote()).len(), 1);
+    }
+
+    #[test]
+    fn format_json_lines() {
+        let mut json = QuoteFormatter::new(OutputFormat::Json);
+        let lines = json.format(&sample_quote());
+        assert_eq!(lines.len(), 1);
+
+        let value: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(value["ticker"], "AAPL");
+        assert_eq!(value["price"], 183.15);
+        assert_eq!(value["bid_size"], 2400);
+        let parsed: StockQuote = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(parsed, sample_quote());
+    }
+
+    #[test]
+    fn format_table_repeats_header() {
+        let specs = [parse_spec_line("PENNY|0.0001|4").unwrap()].into_iter().collect();
+        let mut table = QuoteFormatter::new(OutputFormat::Table).with_prices(PriceFormat::new(specs));
+        let first = table.format(&sample_quote());
+        assert_eq!(first.len(), 2);
+        assert!(first[0].contains("TICKER"));
+        assert!(first[1].contains("       183.12       183.15"));
+        // Все цены тикера печатаются с точностью его инструмента, нули в конце не теряются
+        let penny = StockQuote::new("PENNY", 0.85, 10, 1768380781617).with_book(0.8499, 0.85, 1, 1);
+        assert!(table.format(&penny)[0].contains("       0.8500       0.8499       0.8500"));
+        let round = StockQuote::new("MSFT", 100.5, 10, 1768380781617).with_book(100.25, 100.5, 1, 1);
+        assert!(table.format(&round)[0].contains("       100.50       100.25       100.50"));
+        let rows: usize = (0..17).map(|_| table.format(&sample_quote()).len()).sum();
+        assert_eq!(rows, 17);
+        assert_eq!(table.format(&sample_quote()).len(), 2);
+    }
+
+    #[test]
+    fn dashboard_keeps_one_row_per_ticker() {
+        let started = std::time::Instant::now();
+        let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+        let mut state = DashboardState::new(&tickers, PriceFormat::default(), started);
+
+        let lines = state.render(started);
+        assert!(lines[3].starts_with("AAPL") && lines[3].contains(" - "));
+
+        let later = started + std::time::Duration::from_secs(2);
+        state.update(&sample_quote(), started);
+        state.update(&StockQuote::new("AAPL", 185.15, 4000, 1768380782617), later);
+        // Запоздавшая котировка считается, но не затирает более новую цену
+        state.update_late(&sample_quote(), later);
+        let lines = state.render(later + std::time::Duration::from_millis(500));
+
+        let aapl: Vec<&str> = lines[3].split_whitespace().collect();
+        assert_eq!(&aapl[..4], ["AAPL", "185.15", "+2.00", "1.09%"]);
+        assert_eq!(aapl[8], "0.5");
+        assert!(lines[4].starts_with("MSFT"));
+        assert_eq!(lines.last().unwrap(), "3 updates received");
+        assert_eq!(lines.len(), 3 + 2 + 2);
+    }
+
+    #[test]
+    fn dashboard_prints_ticker_precision() {
+        let started = std::time::Instant::now();
+        let specs = [parse_spec_line("EURUSD|0.0001|4").unwrap()].into_iter().collect();
+        let mut state = DashboardState::new(&["EURUSD".to_string()], PriceFormat::new(specs), started);
+        state.update(&StockQuote::new("EURUSD", 1.08, 10, 1).with_book(1.0799, 1.08, 1, 1), started);
+
+        // Точность берётся из правил инструмента, а не из самих цен
+        let row: Vec<String> = state.render(started)[3].split_whitespace().map(String::from).collect();
+        assert_eq!(&row[..6], ["EURUSD", "1.0800", "0.0000", "0.00%", "1.0799", "1.0800"]);
+        state.update(&StockQuote::new("EURUSD", 1.085, 10, 2).with_book(1.085, 1.0851, 1, 1), started);
+        let row: Vec<String> = state.render(started)[3].split_whitespace().map(String::from).collect();
+        assert_eq!(&row[..6], ["EURUSD", "1.0850", "+0.0050", "0.46%", "1.0850", "1.0851"]);
+    }
+
+    #[test]
+    fn deserialize_sequenced_quote() {
+        let sequenced = SequencedQuote::new(7, StockQuote::new("AAPL", 183.15, 100, 1));
+        let serialized = bincode::serialize(&sequenced).unwrap();
+        let deserialized: SequencedQuote = bincode::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized.seq, 7);
+        assert_eq!(deserialized.quote.ticker, "AAPL");
+    }
+
+    #[test]
+    fn tracker_detects_gaps_duplicates_and_reordering() {
+        let mut tracker = SequenceTracker::new();
+
+        assert_eq!(tracker.on_seq(1), SeqEvent::InOrder);
+        assert_eq!(tracker.on_seq(2), SeqEvent::InOrder);
+        assert_eq!(tracker.on_seq(5), SeqEvent::Gap { from: 3, to: 4 });
+        assert_eq!(tracker.on_seq(4), SeqEvent::Reordered);
+        assert_eq!(tracker.on_seq(4), SeqEvent::Duplicate);
+        assert_eq!(tracker.on_seq(2), SeqEvent::Duplicate);
+        assert_eq!(tracker.on_seq(6), SeqEvent::InOrder);
+
+        assert_eq!(
+            tracker.to_string(),
+            "received 7, last seq 6, missing 1 in 1 gaps, recovered 0, reordered 1, duplicates 2"
+        );
+    }
+
+    #[test]
+    fn tracker_starts_with_gap_when_first_quotes_are_lost() {
+        let mut tracker = SequenceTracker::new();
+
+        assert_eq!(tracker.on_seq(3), SeqEvent::Gap { from: 1, to: 2 });
+        assert_eq!(tracker.on_seq(1), SeqEvent::Reordered);
+        assert_eq!(tracker.on_seq(2), SeqEvent::Reordered);
+        assert_eq!(
+            tracker.to_string(),
+            "received 3, last seq 3, missing 0 in 1 gaps, recovered 0, reordered 2, duplicates 0"
+        );
+    }
+
+    #[test]
+    fn tracker_counts_recovered_quotes() {
+        let mut tracker = SequenceTracker::new();
+        tracker.on_seq(1);
+        assert_eq!(tracker.on_seq(5), SeqEvent::Gap { from: 2, to: 4 });
+
+        assert!(tracker.on_recovered(2));
+        assert!(tracker.on_recovered(3));
+        assert!(!tracker.on_recovered(3));
+        assert_eq!(tracker.on_seq(3), SeqEvent::Duplicate);
+        assert_eq!(
+            tracker.to_string(),
+            "received 3, last seq 5, missing 1 in 1 gaps, recovered 2, reordered 0, duplicates 1"
+        );
+    }
+
+    #[test]
+    fn tracker_bounds_huge_gaps() {
+        let mut tracker = SequenceTracker::new();
+        tracker.on_seq(1);
+
+        // Пропуск почти во весь диапазон номеров не должен зависать или переполняться
+        assert_eq!(tracker.on_seq(u64::MAX), SeqEvent::Gap { from: 2, to: u64::MAX - 1 });
+        assert_eq!(tracker.on_seq(u64::MAX - 1), SeqEvent::Reordered);
+        assert_eq!(tracker.on_seq(3), SeqEvent::Duplicate);
+        assert!(tracker.to_string().contains(&format!("missing {} in 1 gaps", u64::MAX - 3)));
+    }
+
+    #[test]
+    fn spec_line_parsing() {
+        let (ticker, spec) = parse_spec_line("PENNY|0.0001|4").unwrap();
+        assert_eq!(ticker, "PENNY");
+        assert_eq!((spec.tick_size().to_string(), spec.precision()), ("0.0001".to_string(), 4));
+        assert!(parse_spec_line("AAPL|0|2").is_err());
+        assert!(parse_spec_line("AAPL|0.01").is_err());
+    }
+
+    #[test]
+    fn resend_header_parsing() {
+        assert_eq!(parse_resend_header("RESEND 10 20 count: 11\n"), Some(11));
+        assert_eq!(parse_resend_header("RESEND 10 20 count: 0\n"), Some(0));
+        assert_eq!(parse_resend_header("ERROR: no session 3 on this connection\n"), None);
+    }
+
+    #[test]
+    fn multicast_response_parsing() {
+        let channels = parse_multicast_response("MULTICAST AAPL=239.255.0.1:7000 MSFT=239.255.0.3:7002\n").unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].0, "AAPL");
+        assert_eq!(channels[1].1.to_string(), "239.255.0.3:7002");
+
+        assert!(parse_multicast_response("ERROR: multicast is not enabled on this server\n").is_err());
+        assert!(parse_multicast_response("MULTICAST AAPL=nowhere\n").is_err());
+    }
+
+    #[test]
+    fn cli_multicast_replaces_stream_addr() {
+        let cli = CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--multicast"]).unwrap();
+        assert!(cli.multicast);
+        assert_eq!(cli.stream_addr, None);
+
+        assert!(CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL"]).is_err());
+        assert!(CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "-A", "127.0.0.1:5555", "--multicast"]).is_err());
+    }
+
+    #[test]
+    fn session_id_parsing() {
+        let response = "Got STREAM command addr: udp://127.0.0.1:5555 tickers: AAPL server: 127.0.0.1:62005 session: 17\n";
+        assert_eq!(parse_session_id(response), Some(17));
+        assert_eq!(parse_session_id("Got STREAM command addr: tcp:// tickers: AAPL server: 127.0.0.1:62005\n"), None);
+        assert_eq!(parse_session_id("ERROR: Failed to start streaming\n"), None);
+    }
+
+    #[test]
+    fn tickers_reply_parsing() {
+        assert_eq!(parse_tickers_reply("TICKERS AAPL,MSFT\n"), Some(vec!["AAPL".to_string(), "MSFT".to_string()]));
+        assert_eq!(parse_tickers_reply("TICKERS \n"), Some(vec![]));
+        assert_eq!(parse_tickers_reply("ERROR: no session 3 on this connection\n"), None);
+    }
+
+    #[test]
+    fn cli_tcp_transport_needs_no_stream_addr() {
+        let cli = CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL"]);
+        assert!(cli.is_err());
+
+        let cli = CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "-A", "127.0.0.1:5555"]).unwrap();
+        assert_eq!(cli.transport, Transport::Udp);
+        assert!(CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--transport", "udp"]).is_err());
+
+        let cli = CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--transport", "tcp"]).unwrap();
+        assert_eq!(cli.transport, Transport::Tcp);
+        assert_eq!(cli.stream_addr, None);
+
+        assert!(CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--transport", "tcp", "--multicast"]).is_err());
+        assert!(CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--transport", "sctp"]).is_err());
+    }
+
+    #[test]
+    fn cli_stream_options() {
+        let cli = CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--transport", "tcp"]).unwrap();
+        assert_eq!(cli.stream_options(), "");
+
+        let cli = CliArgs::try_parse_from([
+            "quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--transport", "tcp", "--max-rate", "50", "--throttle-ms", "250",
+        ])
+        .unwrap();
+        assert_eq!(cli.stream_options(), " MAXRATE=50/s THROTTLE=250ms");
+        assert!(CliArgs::try_parse_from(["quote_client", "-H", "h", "-p", "1", "-T", "AAPL", "--multicast", "--max-rate", "5"]).is_err());
+    }
+
+    #[test]
+    fn tracker_joined_mid_stream_starts_at_first_seq() {
+        let mut tracker = SequenceTracker::joined_mid_stream();
+
+        assert_eq!(tracker.on_seq(100), SeqEvent::InOrder);
+        assert_eq!(tracker.on_seq(101), SeqEvent::InOrder);
+        assert_eq!(tracker.on_seq(103), SeqEvent::Gap { from: 102, to: 102 });
+        assert_eq!(tracker.on_seq(50), SeqEvent::Duplicate);
+    }
+
+    #[test]
+    fn snapshot_header_parsing() {
+        assert_eq!(parse_snapshot_header("SNAPSHOT count: 3\n"), Some(3));
+        assert_eq!(parse_snapshot_header("SNAPSHOT count: 0\n"), Some(0));
+        assert_eq!(parse_snapshot_header("ERROR: use like 'SNAPSHOT AAPL,TSLA'\n"), None);
     }
 }
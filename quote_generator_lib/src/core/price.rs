@@ -0,0 +1,233 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Fixed-point price with `Price::DECIMALS` decimal places
+///
+/// Binary formats carry the raw integer, so quotes never carry float noise on the wire.
+/// Human readable formats (JSON and friends) carry a decimal number.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(i64);
+
+impl Price {
+    /// Number of decimal places a price can represent
+    pub const DECIMALS: u8 = 4;
+    /// Raw units per 1.0
+    pub const SCALE: i64 = 10_i64.pow(Self::DECIMALS as u32);
+    pub const ZERO: Price = Price(0);
+
+    /// Creates a price from raw units of `1 / Price::SCALE`
+    pub const fn from_raw(raw: i64) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw units of `1 / Price::SCALE`
+    pub const fn raw(&self) -> i64 {
+        self.0
+    }
+
+    /// Converts a float to the nearest representable price
+    pub fn from_f64(value: f64) -> Self {
+        Self((value * Self::SCALE as f64).round() as i64)
+    }
+
+    /// Converts the price to a float
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64 / Self::SCALE as f64
+    }
+
+    /// Rounds the price down to a multiple of the tick size
+    pub fn floor_to_tick(&self, tick: Price) -> Self {
+        Self(self.0.div_euclid(tick.0) * tick.0)
+    }
+
+    /// Rounds the price up to a multiple of the tick size
+    pub fn ceil_to_tick(&self, tick: Price) -> Self {
+        Self(-(-self.0).div_euclid(tick.0) * tick.0)
+    }
+
+    /// Rounds the price to the nearest multiple of the tick size
+    pub fn round_to_tick(&self, tick: Price) -> Self {
+        Self((self.0 + tick.0 / 2).div_euclid(tick.0) * tick.0)
+    }
+
+    /// Number of decimal places needed to print the price exactly
+    pub fn decimals(&self) -> u8 {
+        let mut raw = self.0;
+        let mut decimals = Self::DECIMALS;
+        while decimals > 0 && raw % 10 == 0 {
+            raw /= 10;
+            decimals -= 1;
+        }
+        decimals
+    }
+
+    /// Formats the price with exactly `precision` decimal places, rounding half away from zero
+    ///
+    /// Precision above `Price::DECIMALS` is padded with zeros
+    pub fn format(&self, precision: u8) -> String {
+        // Округляем до последнего печатаемого знака, а не отбрасываем лишние
+        let unit = 10_u64.pow(Self::DECIMALS.saturating_sub(precision) as u32);
+        let rounded = (self.0.unsigned_abs() + unit / 2) / unit * unit;
+        let digits = format!("{:0width$}", rounded, width = Self::DECIMALS as usize + 1);
+        let (int_part, frac_part) = digits.split_at(digits.len() - Self::DECIMALS as usize);
+        let sign = if self.0 < 0 && rounded > 0 { "-" } else { "" };
+        let frac: String = frac_part
+            .chars()
+            .chain(std::iter::repeat('0'))
+            .take(precision as usize)
+            .collect();
+
+        if frac.is_empty() {
+            format!("{}{}", sign, int_part)
+        } else {
+            format!("{}{}.{}", sign, int_part, frac)
+        }
+    }
+}
+
+impl From<f64> for Price {
+    fn from(value: f64) -> Self {
+        Self::from_f64(value)
+    }
+}
+
+impl Add for Price {
+    type Output = Price;
+
+    fn add(self, rhs: Price) -> Price {
+        Price(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Price {
+    type Output = Price;
+
+    fn sub(self, rhs: Price) -> Price {
+        Price(self.0 - rhs.0)
+    }
+}
+
+impl PartialEq<f64> for Price {
+    fn eq(&self, other: &f64) -> bool {
+        self.to_f64() == *other
+    }
+}
+
+impl PartialOrd<f64> for Price {
+    fn partial_cmp(&self, other: &f64) -> Option<Ordering> {
+        self.to_f64().partial_cmp(other)
+    }
+}
+
+/// Prints the shortest exact decimal form, e.g. `123.4` rather than `123.4000`
+impl Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let full = self.format(Self::DECIMALS);
+        let trimmed = full.trim_end_matches('0').trim_end_matches('.');
+        // pad учитывает ширину и выравнивание, например `{:>12}` в таблицах
+        f.pad(trimmed)
+    }
+}
+
+impl std::fmt::Debug for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// Parses a decimal string exactly, without going through a float
+impl FromStr for Price {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid price: '{}'", s);
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (int_part, frac_part) = match digits.split_once('.') {
+            // Точка без дробной части, как в "1.", не считается ценой
+            Some((_, "")) => return Err(invalid()),
+            Some(parts) => parts,
+            None => (digits, ""),
+        };
+
+        let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if int_part.is_empty() || !all_digits(int_part) || !all_digits(frac_part) {
+            return Err(invalid());
+        }
+        if frac_part.len() > Self::DECIMALS as usize {
+            return Err(format!("'{}' has more than {} decimal places", s, Self::DECIMALS));
+        }
+
+        let int: i64 = int_part.parse().map_err(|_| invalid())?;
+        let frac: i64 = if frac_part.is_empty() {
+            0
+        } else {
+            frac_part.parse::<i64>().map_err(|_| invalid())?
+                * 10_i64.pow((Self::DECIMALS as usize - frac_part.len()) as u32)
+        };
+
+        let raw = int
+            .checked_mul(Self::SCALE)
+            .and_then(|v| v.checked_add(frac))
+            .ok_or_else(invalid)?;
+        Ok(Price(if negative { -raw } else { raw }))
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_f64(self.to_f64())
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(PriceVisitor)
+        } else {
+            i64::deserialize(deserializer).map(Price)
+        }
+    }
+}
+
+/// Accepts prices written as numbers (`183.13`, `100`) or decimal strings (`"183.13"`)
+struct PriceVisitor;
+
+impl Visitor<'_> for PriceVisitor {
+    type Value = Price;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a price as a number or a decimal string")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Price, E> {
+        v.checked_mul(Price::SCALE)
+            .map(Price)
+            .ok_or_else(|| E::custom("price out of range"))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Price, E> {
+        i64::try_from(v)
+            .map_err(E::custom)
+            .and_then(|v| self.visit_i64(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Price, E> {
+        Ok(Price::from_f64(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Price, E> {
+        v.parse().map_err(E::custom)
+    }
+}
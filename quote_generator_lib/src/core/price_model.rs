@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use rand::RngCore;
+use rand_distr::{Distribution, StandardNormal};
+
+/// Trading minutes in a year (252 sessions of 6.5 hours)
+const TRADING_MINUTES_PER_YEAR: f64 = 252.0 * 390.0;
+
+/// Model that evolves the price of an instrument from one tick to the next
+pub trait PriceModel: Send {
+    /// Returns the next price for the given ticker based on its current price
+    ///
+    /// All randomness must be drawn from `rng` so seeded generators stay reproducible
+    fn next_price(&mut self, rng: &mut dyn RngCore, ticker: &str, price: f64) -> f64;
+}
+
+/// Annualized drift and volatility of a geometric Brownian motion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GbmParams {
+    pub drift: f64,
+    pub volatility: f64,
+}
+
+impl GbmParams {
+    /// Creates new GBM parameters from annualized drift and volatility
+    pub fn new(drift: f64, volatility: f64) -> Self {
+        Self { drift, volatility }
+    }
+}
+
+impl Default for GbmParams {
+    fn default() -> Self {
+        Self::new(0.05, 0.25)
+    }
+}
+
+/// Geometric Brownian motion price model
+///
+/// Prices follow `S(t+dt) = S(t) * exp((mu - sigma^2 / 2) * dt + sigma * sqrt(dt) * Z)`,
+/// so returns are proportional to the price level and the price never crosses zero
+pub struct GbmModel {
+    /// Time step between two ticks in years
+    dt: f64,
+    default_params: GbmParams,
+    params: HashMap<String, GbmParams>,
+}
+
+impl GbmModel {
+    /// Creates a new GbmModel with one tick per trading minute and default parameters
+    pub fn new() -> Self {
+        Self {
+            dt: 1.0 / TRADING_MINUTES_PER_YEAR,
+            default_params: GbmParams::default(),
+            params: HashMap::new(),
+        }
+    }
+
+    /// Sets the time step between two ticks in years
+    pub fn with_dt(mut self, dt: f64) -> Self {
+        self.dt = dt;
+        self
+    }
+
+    /// Sets the parameters used for tickers without explicit parameters
+    pub fn with_default_params(mut self, params: GbmParams) -> Self {
+        self.default_params = params;
+        self
+    }
+
+    /// Sets drift and volatility for a single ticker
+    pub fn with_params(mut self, ticker: &str, params: GbmParams) -> Self {
+        self.params.insert(ticker.to_string(), params);
+        self
+    }
+
+    /// Returns the parameters used for the given ticker
+    pub fn params(&self, ticker: &str) -> GbmParams {
+        self.params
+            .get(ticker)
+            .copied()
+            .unwrap_or(self.default_params)
+    }
+}
+
+impl Default for GbmModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceModel for GbmModel {
+    fn next_price(&mut self, rng: &mut dyn RngCore, ticker: &str, price: f64) -> f64 {
+        let GbmParams { drift, volatility } = self.params(ticker);
+        let z: f64 = StandardNormal.sample(rng);
+
+        let exponent = (drift - volatility * volatility / 2.0) * self.dt + volatility * self.dt.sqrt() * z;
+        price * exponent.exp()
+    }
+}
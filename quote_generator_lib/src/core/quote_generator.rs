@@ -1,57 +1,167 @@
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 
-use rand;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use crate::core::types::StockQuote;
+use crate::core::price::Price;
+use crate::core::price_model::{GbmModel, PriceModel};
+use crate::core::types::{InstrumentSpec, LiquidityTier, StockQuote};
+
+/// Number of shares in a round lot
+const ROUND_LOT: u32 = 100;
+
+/// Source of quote timestamps in milliseconds since UNIX epoch
+pub type Clock = Box<dyn FnMut() -> u64 + Send>;
 
 /// Generator for creating and updating stock quotes
 pub struct QuoteGenerator {
     pub quotes: HashMap<String, StockQuote>,
+    /// Unrounded mid prices the price model evolves, quotes are derived from them
+    mids: HashMap<String, f64>,
+    specs: HashMap<String, InstrumentSpec>,
+    tiers: HashMap<String, LiquidityTier>,
+    start_prices: HashMap<String, Price>,
+    model: Box<dyn PriceModel>,
+    rng: Box<dyn RngCore + Send>,
+    clock: Clock,
 }
 
 impl QuoteGenerator {
-    /// Creates a new QuoteGenerator with an empty quotes map
+    /// Creates a new QuoteGenerator with an empty quotes map, the default GBM price model
+    /// and a randomly seeded RNG
     pub fn new() -> Self {
+        Self::from_rng(Box::new(ChaCha8Rng::from_entropy()))
+    }
+
+    /// Creates a new QuoteGenerator whose prices and volumes are fully determined by the seed
+    ///
+    /// Two generators created with the same seed produce the same sequence of quotes
+    /// for the same sequence of tickers (timestamps excepted, see `with_clock`)
+    pub fn from_seed(seed: u64) -> Self {
+        Self::from_rng(Box::new(ChaCha8Rng::seed_from_u64(seed)))
+    }
+
+    /// Creates a new QuoteGenerator drawing all randomness from the given RNG
+    pub fn from_rng(rng: Box<dyn RngCore + Send>) -> Self {
         QuoteGenerator {
             quotes: HashMap::new(),
+            mids: HashMap::new(),
+            specs: HashMap::new(),
+            tiers: HashMap::new(),
+            start_prices: HashMap::new(),
+            model: Box::new(GbmModel::default()),
+            rng,
+            clock: Box::new(crate::get_current_timestamp),
         }
     }
 
-    fn generate_volume (ticker: &str) -> u32 {
-        match ticker {
+    /// Replaces the price model driving the generator
+    pub fn with_model(mut self, model: Box<dyn PriceModel>) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Sets the price rules of an instrument, tickers without a spec trade as `InstrumentSpec::US_EQUITY`
+    pub fn with_instrument(mut self, ticker: &str, spec: InstrumentSpec) -> Self {
+        self.specs.insert(ticker.to_string(), spec);
+        self
+    }
+
+    /// Sets the liquidity tier of an instrument, overriding `LiquidityTier::for_ticker`
+    pub fn with_tier(mut self, ticker: &str, tier: LiquidityTier) -> Self {
+        self.tiers.insert(ticker.to_string(), tier);
+        self
+    }
+
+    /// Sets the mid price the first quote of an instrument is generated around
+    ///
+    /// Tickers without a starting price start at a random price between 100 and 200
+    pub fn with_start_price(mut self, ticker: &str, price: Price) -> Self {
+        self.start_prices.insert(ticker.to_string(), price);
+        self
+    }
+
+    /// Replaces the wall clock used to timestamp quotes
+    ///
+    /// Combined with `from_seed` this makes the generated quotes fully reproducible
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn generate_volume (rng: &mut dyn RngCore, tier: LiquidityTier) -> u32 {
+        match tier {
              // Популярные акции имеют больший объём
-            "AAPL" | "MSFT" | "TSLA" => 1000 + (rand::random::<f64>() * 5000.0) as u32,
-            "GOOGL" | "AMZN" | "FB" => 500 + (rand::random::<f64>() * 2000.0) as u32,
+            LiquidityTier::High => 1000 + (rng.r#gen::<f64>() * 5000.0) as u32,
+            LiquidityTier::Medium => 500 + (rng.r#gen::<f64>() * 2000.0) as u32,
             // Обычные акции - средний объём
-            _ => 100 + (rand::random::<f64>() * 1000.0) as u32,
+            LiquidityTier::Low => 100 + (rng.r#gen::<f64>() * 1000.0) as u32,
         }
     }
 
+    /// Quotes a book around the mid price and prints a trade on one of its sides
+    ///
+    /// The spread is proportional to the price and widens for less liquid tiers.
+    /// Bid and ask are rounded away from the mid to the tick size, at least one tick apart
+    fn quote_book(
+        rng: &mut dyn RngCore,
+        tier: LiquidityTier,
+        spec: InstrumentSpec,
+        mut quote: StockQuote,
+        mid: f64,
+    ) -> StockQuote {
+        let tick = spec.tick_size();
+        let half_spread = mid * tier.half_spread() * (0.5 + rng.r#gen::<f64>());
+        let bid = Price::from_f64(mid - half_spread).floor_to_tick(tick).max(tick);
+        let ask = Price::from_f64(mid + half_spread).ceil_to_tick(tick).max(bid + tick);
+        let bid_size = rng.gen_range(1..=tier.max_lots()) * ROUND_LOT;
+        let ask_size = rng.gen_range(1..=tier.max_lots()) * ROUND_LOT;
+        let last_size = rng.gen_range(1..=ROUND_LOT);
+
+        quote = quote.with_book(bid, ask, bid_size, ask_size);
+        // Сделка проходит либо по биду, либо по аску
+        quote.price = if rng.gen_bool(0.5) { quote.bid } else { quote.ask };
+        quote.with_last_size(last_size)
+    }
+
     /// Generates or updates a quote for the given ticker symbol
     /// 
     /// Returns the updated quote or None if generation fails
     pub fn generate_quote(&mut self, ticker: &str) -> Option<StockQuote> {
-        let _ = self
-            .quotes
-            .entry(ticker.to_string())
-            .and_modify(|q| {
+        let timestamp = (self.clock)();
+        let rng = self.rng.as_mut();
+        let tier = self
+            .tiers
+            .get(ticker)
+            .copied()
+            .unwrap_or_else(|| LiquidityTier::for_ticker(ticker));
+        let spec = self.specs.get(ticker).copied().unwrap_or_default();
+
+        let quote = match self.quotes.entry(ticker.to_string()) {
+            Entry::Occupied(entry) => {
+                let q = entry.into_mut();
+                let mid = self.mids.entry(ticker.to_string()).or_insert_with(|| q.mid());
                 // Обновление цены, объема и временной метки для имитации реальных данных
-                q.price += (rand::random::<f64>() - 0.5) * 2.0; // случайное изменение цены
-                q.volume += rand::random::<u32>() % 100; // случайное изменение объем
-                q.timestamp = crate::get_current_timestamp(); //текущее время
-            })
-            .or_insert(StockQuote::new(
-                ticker,
-                100.0 + rand::random::<f64>() * 100.0,
-                Self::generate_volume(ticker),
-                crate::get_current_timestamp(),
-            ));
-
-        self.quotes.get(ticker).cloned()
-
-        /*self.quotes
-            .get(&ticker.to_string())
-            .map_or(None, |q| Some(q.clone()))*/
+                *mid = self.model.next_price(rng, ticker, *mid); // изменение цены по модели
+                *q = Self::quote_book(rng, tier, spec, q.clone(), *mid);
+                q.volume += q.last_size; // объём растёт на размер сделки
+                q.timestamp = timestamp; //текущее время
+                q
+            }
+            Entry::Vacant(entry) => {
+                let mid = match self.start_prices.get(ticker) {
+                    Some(price) => price.to_f64(),
+                    None => 100.0 + rng.r#gen::<f64>() * 100.0,
+                };
+                let volume = Self::generate_volume(rng, tier);
+                let quote = StockQuote::new(ticker, mid, volume, timestamp);
+                self.mids.insert(ticker.to_string(), mid);
+                entry.insert(Self::quote_book(rng, tier, spec, quote, mid))
+            }
+        };
+
+        Some(quote.clone())
     }
 }
 
@@ -0,0 +1,219 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::core::quote_generator::QuoteGenerator;
+use crate::core::recording::ReplaySpeed;
+use crate::core::types::StockQuote;
+
+/// Quotes buffered between a merged source and each of its producer threads
+const MERGED_SOURCE_CAPACITY: usize = 1024;
+
+/// Producer of quotes driven by the streaming loop
+///
+/// Sources pace themselves: `next_quote` blocks until the next quote is due.
+pub trait QuoteSource: Send {
+    /// Returns the next quote, an error to report, or None once the source is exhausted
+    fn next_quote(&mut self) -> Option<io::Result<StockQuote>>;
+
+    /// Short human readable description used in logs
+    fn describe(&self) -> String;
+}
+
+/// Random quotes from a `QuoteGenerator`, cycling through the tickers
+///
+/// Waits `delay` between two quotes and an extra `interval` after each pass over the tickers
+pub struct GeneratorSource {
+    generator: QuoteGenerator,
+    tickers: Vec<String>,
+    next: usize,
+    delay: Duration,
+    interval: Duration,
+    pending_sleep: Duration,
+}
+
+impl GeneratorSource {
+    /// Creates a new GeneratorSource without any pause between quotes
+    pub fn new(generator: QuoteGenerator, tickers: Vec<String>) -> Self {
+        Self {
+            generator,
+            tickers,
+            next: 0,
+            delay: Duration::ZERO,
+            interval: Duration::ZERO,
+            pending_sleep: Duration::ZERO,
+        }
+    }
+
+    /// Sets the pause between two quotes
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Sets the extra pause after each pass over all tickers
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl QuoteSource for GeneratorSource {
+    fn next_quote(&mut self) -> Option<io::Result<StockQuote>> {
+        if self.tickers.is_empty() {
+            return None;
+        }
+        thread::sleep(self.pending_sleep);
+
+        let ticker = &self.tickers[self.next];
+        let quote = self.generator.generate_quote(ticker);
+
+        self.next = (self.next + 1) % self.tickers.len();
+        self.pending_sleep = if self.next == 0 {
+            self.delay + self.interval
+        } else {
+            self.delay
+        };
+
+        match quote {
+            Some(quote) => Some(Ok(quote)),
+            None => Some(Err(io::Error::other(format!("failed to generate quote for {}", ticker)))),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("generator for {} tickers", self.tickers.len())
+    }
+}
+
+/// Recorded quotes replayed at their original pace, a multiple of it, or as fast as possible
+///
+/// Replayed quotes keep their recorded timestamps. The source ends after the first read error.
+pub struct ReplaySource {
+    quotes: Box<dyn Iterator<Item = io::Result<StockQuote>> + Send>,
+    speed: ReplaySpeed,
+    name: String,
+    started: Option<(Instant, u64)>,
+    failed: bool,
+}
+
+impl ReplaySource {
+    /// Creates a new ReplaySource over recorded quotes, e.g. a `QuoteReader`
+    pub fn new<I>(name: &str, quotes: I, speed: ReplaySpeed) -> Self
+    where
+        I: Iterator<Item = io::Result<StockQuote>> + Send + 'static,
+    {
+        Self {
+            quotes: Box::new(quotes),
+            speed,
+            name: name.to_string(),
+            started: None,
+            failed: false,
+        }
+    }
+}
+
+impl QuoteSource for ReplaySource {
+    fn next_quote(&mut self) -> Option<io::Result<StockQuote>> {
+        if self.failed {
+            return None;
+        }
+
+        let quote = match self.quotes.next()? {
+            Ok(quote) => quote,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+
+        // Сохраняем исходные интервалы между котировками с учётом скорости
+        let (started, first_timestamp) = *self.started.get_or_insert((Instant::now(), quote.timestamp));
+        let due = self.speed.due_after(quote.timestamp.saturating_sub(first_timestamp));
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            thread::sleep(wait);
+        }
+
+        Some(Ok(quote))
+    }
+
+    fn describe(&self) -> String {
+        format!("replay of {} at {:?} speed", self.name, self.speed)
+    }
+}
+
+/// Several sources running concurrently, their quotes interleaved as they come
+///
+/// Each source runs on its own thread, the merged source ends once all of them are exhausted
+pub struct MergedSource {
+    receiver: Receiver<io::Result<StockQuote>>,
+    description: String,
+}
+
+impl MergedSource {
+    /// Starts all sources and merges their output
+    pub fn new(sources: Vec<Box<dyn QuoteSource>>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(MERGED_SOURCE_CAPACITY);
+        let description = format!(
+            "merge of [{}]",
+            sources.iter().map(|s| s.describe()).collect::<Vec<_>>().join(", ")
+        );
+
+        for mut source in sources {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                while let Some(quote) = source.next_quote() {
+                    if sender.send(quote).is_err() {
+                        // Объединённый источник удалён, останавливаемся
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self { receiver, description }
+    }
+}
+
+impl QuoteSource for MergedSource {
+    fn next_quote(&mut self) -> Option<io::Result<StockQuote>> {
+        self.receiver.recv().ok()
+    }
+
+    fn describe(&self) -> String {
+        self.description.clone()
+    }
+}
+
+/// Several sources played one after another, e.g. a recorded morning followed by live generation
+pub struct ChainedSource {
+    sources: Vec<Box<dyn QuoteSource>>,
+    current: usize,
+}
+
+impl ChainedSource {
+    /// Creates a new ChainedSource playing the sources in order
+    pub fn new(sources: Vec<Box<dyn QuoteSource>>) -> Self {
+        Self { sources, current: 0 }
+    }
+}
+
+impl QuoteSource for ChainedSource {
+    fn next_quote(&mut self) -> Option<io::Result<StockQuote>> {
+        while let Some(source) = self.sources.get_mut(self.current) {
+            match source.next_quote() {
+                Some(quote) => return Some(quote),
+                None => self.current += 1,
+            }
+        }
+        None
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "chain of [{}]",
+            self.sources.iter().map(|s| s.describe()).collect::<Vec<_>>().join(", ")
+        )
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::core::types::StockQuote;
+
+/// Largest accepted length of one binary record, anything above means a corrupted file
+const MAX_BINARY_RECORD_LEN: usize = 64 * 1024;
+
+/// Format of a recorded quote file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// One quote per line in the pipe-delimited `StockQuote` `Display` format
+    Text,
+    /// Quotes serialized with bincode, each prefixed with its length as a little-endian `u32`
+    Binary,
+}
+
+impl RecordFormat {
+    /// Picks the format from the file extension: `.bin` files are binary, everything else is text
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("bin") => RecordFormat::Binary,
+            _ => RecordFormat::Text,
+        }
+    }
+
+    /// File extension used for files in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Text => "csv",
+            RecordFormat::Binary => "bin",
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" | "text" => Ok(RecordFormat::Text),
+            "bin" | "bincode" | "binary" => Ok(RecordFormat::Binary),
+            other => Err(format!("unknown record format '{}', expected csv or bin", other)),
+        }
+    }
+}
+
+/// Writes one quote in the given format
+pub fn write_quote<W: Write>(writer: &mut W, format: RecordFormat, quote: &StockQuote) -> io::Result<()> {
+    match format {
+        RecordFormat::Text => writeln!(writer, "{}", quote),
+        RecordFormat::Binary => {
+            let encoded = bincode::serialize(quote).map_err(io::Error::other)?;
+            writer.write_all(&(encoded.len() as u32).to_le_bytes())?;
+            writer.write_all(&encoded)
+        }
+    }
+}
+
+/// Reads recorded quotes back, in either format
+///
+/// Empty lines and lines starting with `#` are skipped in text files
+pub struct QuoteReader<R> {
+    reader: R,
+    format: RecordFormat,
+    line: usize,
+}
+
+impl QuoteReader<BufReader<File>> {
+    /// Opens a recorded file, picking the format from its extension
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::new(BufReader::new(file), RecordFormat::from_path(path)))
+    }
+}
+
+impl<R: BufRead> QuoteReader<R> {
+    /// Creates a new QuoteReader over the given reader
+    pub fn new(reader: R, format: RecordFormat) -> Self {
+        Self {
+            reader,
+            format,
+            line: 0,
+        }
+    }
+
+    fn read_text(&mut self) -> io::Result<Option<StockQuote>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            return StockQuote::from_string(trimmed).map(Some).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: invalid quote '{}'", self.line, trimmed),
+                )
+            });
+        }
+    }
+
+    fn read_binary(&mut self) -> io::Result<Option<StockQuote>> {
+        let mut len = [0u8; 4];
+        // Чистый конец файла возможен только на границе записи
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut len)?;
+        self.line += 1;
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_BINARY_RECORD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record {}: length {} exceeds {} bytes", self.line, len, MAX_BINARY_RECORD_LEN),
+            ));
+        }
+
+        let mut payload = vec![0u8; len];
+        self.reader.read_exact(&mut payload)?;
+        bincode::deserialize(&payload)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("record {}: {}", self.line, e)))
+    }
+}
+
+impl<R: BufRead> Iterator for QuoteReader<R> {
+    type Item = io::Result<StockQuote>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match self.format {
+            RecordFormat::Text => self.read_text(),
+            RecordFormat::Binary => self.read_binary(),
+        };
+        result.transpose()
+    }
+}
+
+/// Pace at which recorded quotes are replayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Keep the original spacing between quote timestamps, divided by the factor
+    Multiplier(f64),
+    /// Publish quotes as fast as possible
+    Max,
+}
+
+impl ReplaySpeed {
+    /// Returns how long after the start of the replay a quote is due
+    ///
+    /// `elapsed_ms` is the distance between the quote timestamp and the first replayed timestamp
+    pub fn due_after(&self, elapsed_ms: u64) -> Duration {
+        match self {
+            ReplaySpeed::Multiplier(factor) => Duration::from_secs_f64(elapsed_ms as f64 / 1000.0 / factor),
+            ReplaySpeed::Max => Duration::ZERO,
+        }
+    }
+}
+
+impl Default for ReplaySpeed {
+    fn default() -> Self {
+        ReplaySpeed::Multiplier(1.0)
+    }
+}
+
+/// Parses `max`, `original` or a multiplier such as `1x`, `10x`, `0.5`
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "max" => Ok(ReplaySpeed::Max),
+            "original" => Ok(ReplaySpeed::Multiplier(1.0)),
+            _ => {
+                let factor: f64 = s
+                    .strip_suffix('x')
+                    .unwrap_or(&s)
+                    .parse()
+                    .map_err(|_| format!("invalid replay speed '{}', expected max, original or e.g. 10x", s))?;
+                if factor.is_finite() && factor > 0.0 {
+                    Ok(ReplaySpeed::Multiplier(factor))
+                } else {
+                    Err(format!("replay speed must be positive, got '{}'", s))
+                }
+            }
+        }
+    }
+}
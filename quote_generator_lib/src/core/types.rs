@@ -1,48 +1,228 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
-/// Represents a stock quote with ticker, price, volume, and timestamp
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::core::price::Price;
+
+/// Price rules of an instrument
+///
+/// Always valid: built by `InstrumentSpec::new`, deserialization included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawInstrumentSpec")]
+pub struct InstrumentSpec {
+    /// Minimal price increment, every generated price is a multiple of it
+    tick_size: Price,
+    /// Number of decimal places prices are printed with
+    precision: u8,
+}
+
+/// Unchecked fields of a deserialized InstrumentSpec
+#[derive(Deserialize)]
+struct RawInstrumentSpec {
+    tick_size: Price,
+    precision: u8,
+}
+
+impl TryFrom<RawInstrumentSpec> for InstrumentSpec {
+    type Error = String;
+
+    fn try_from(raw: RawInstrumentSpec) -> Result<Self, Self::Error> {
+        InstrumentSpec::new(raw.tick_size, raw.precision)
+    }
+}
+
+impl InstrumentSpec {
+    /// Spec of a US equity trading above $1: one cent ticks, two decimals
+    pub const US_EQUITY: InstrumentSpec = InstrumentSpec {
+        tick_size: Price::from_raw(Price::SCALE / 100),
+        precision: 2,
+    };
+
+    /// Creates a new InstrumentSpec
+    ///
+    /// Returns an error if the tick is not positive or cannot be printed with the given precision
+    pub fn new(tick_size: Price, precision: u8) -> Result<Self, String> {
+        if tick_size <= Price::ZERO {
+            return Err(format!("tick size must be positive, got {}", tick_size));
+        }
+        if precision > Price::DECIMALS {
+            return Err(format!("precision must be at most {}, got {}", Price::DECIMALS, precision));
+        }
+        let unit = Price::SCALE / 10_i64.pow(precision as u32);
+        if tick_size.raw() % unit != 0 {
+            return Err(format!("tick size {} needs more than {} decimals", tick_size, precision));
+        }
+        Ok(Self { tick_size, precision })
+    }
+
+    /// Minimal price increment, every generated price is a multiple of it
+    pub fn tick_size(&self) -> Price {
+        self.tick_size
+    }
+
+    /// Number of decimal places prices are printed with
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Formats a price with the instrument precision
+    pub fn format_price(&self, price: Price) -> String {
+        price.format(self.precision)
+    }
+}
+
+impl Default for InstrumentSpec {
+    fn default() -> Self {
+        Self::US_EQUITY
+    }
+}
+
+/// Liquidity tier of an instrument, drives traded volumes, book sizes and spreads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiquidityTier {
+    High,
+    Medium,
+    Low,
+}
+
+impl LiquidityTier {
+    /// Returns the built-in liquidity tier for the given ticker
+    pub fn for_ticker(ticker: &str) -> Self {
+        match ticker {
+            // Популярные акции имеют больший объём
+            "AAPL" | "MSFT" | "TSLA" => LiquidityTier::High,
+            "GOOGL" | "AMZN" | "FB" => LiquidityTier::Medium,
+            // Обычные акции - средний объём
+            _ => LiquidityTier::Low,
+        }
+    }
+
+    /// Typical half of the bid/ask spread as a fraction of the price
+    pub fn half_spread(&self) -> f64 {
+        match self {
+            LiquidityTier::High => 0.0001,
+            LiquidityTier::Medium => 0.0003,
+            LiquidityTier::Low => 0.0008,
+        }
+    }
+
+    /// Largest number of round lots (100 shares) quoted on one side of the book
+    pub fn max_lots(&self) -> u32 {
+        match self {
+            LiquidityTier::High => 50,
+            LiquidityTier::Medium => 20,
+            LiquidityTier::Low => 5,
+        }
+    }
+}
+
+impl FromStr for LiquidityTier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "high" => Ok(LiquidityTier::High),
+            "medium" => Ok(LiquidityTier::Medium),
+            "low" => Ok(LiquidityTier::Low),
+            other => Err(format!("unknown liquidity tier '{}', expected high, medium or low", other)),
+        }
+    }
+}
+
+/// Represents a stock quote with the last trade, top of book, volume, and timestamp
+///
+/// `price` and `last_size` describe the last trade, `volume` is the accumulated traded volume.
+/// Prices are fixed-point, so the wire format never carries float noise
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StockQuote {
     pub ticker: String,
-    pub price: f64,
+    pub price: Price,
     pub volume: u32,
     pub timestamp: u64,
+    pub bid: Price,
+    pub ask: Price,
+    pub bid_size: u32,
+    pub ask_size: u32,
+    pub last_size: u32,
 }
 
 impl StockQuote {
-    /// Creates a new StockQuote
-    pub fn new(ticker: &str, price: f64, volume: u32, timestamp: u64) -> Self {
+    /// Creates a new StockQuote with an empty book quoted at the trade price
+    pub fn new(ticker: &str, price: impl Into<Price>, volume: u32, timestamp: u64) -> Self {
+        let price = price.into();
         Self {
             ticker: ticker.to_string(),
             price,
             volume,
             timestamp,
+            bid: price,
+            ask: price,
+            bid_size: 0,
+            ask_size: 0,
+            last_size: 0,
         }
     }
 
+    /// Sets the top of book
+    pub fn with_book(mut self, bid: impl Into<Price>, ask: impl Into<Price>, bid_size: u32, ask_size: u32) -> Self {
+        self.bid = bid.into();
+        self.ask = ask.into();
+        self.bid_size = bid_size;
+        self.ask_size = ask_size;
+        self
+    }
+
+    /// Sets the size of the last trade
+    pub fn with_last_size(mut self, last_size: u32) -> Self {
+        self.last_size = last_size;
+        self
+    }
+
+    /// Returns the midpoint between bid and ask
+    pub fn mid(&self) -> f64 {
+        (self.bid.to_f64() + self.ask.to_f64()) / 2.0
+    }
+
+    /// Returns the bid/ask spread
+    pub fn spread(&self) -> Price {
+        self.ask - self.bid
+    }
+
     /// Parses a StockQuote from a pipe-delimited string
     /// 
+    /// Accepts both the full format and the legacy `ticker|price|volume|timestamp` one,
+    /// in which case the book is quoted at the trade price.
     /// Returns None if the string format is invalid
     pub fn from_string(s: &str) -> Option<Self> {
         let parts: Vec<&str> = s.split('|').collect();
-        if parts.len() == 4 {
-            Some(StockQuote {
+        match parts.len() {
+            4 => Some(StockQuote::new(
+                parts[0],
+                parts[1].parse::<Price>().ok()?,
+                parts[2].parse().ok()?,
+                parts[3].parse().ok()?,
+            )),
+            9 => Some(StockQuote {
                 ticker: parts[0].to_string(),
                 price: parts[1].parse().ok()?,
                 volume: parts[2].parse().ok()?,
                 timestamp: parts[3].parse().ok()?,
-            })
-        } else {
-            None
+                bid: parts[4].parse().ok()?,
+                ask: parts[5].parse().ok()?,
+                bid_size: parts[6].parse().ok()?,
+                ask_size: parts[7].parse().ok()?,
+                last_size: parts[8].parse().ok()?,
+            }),
+            _ => None,
         }
     }
 }
 
 impl Default for StockQuote {
     fn default() -> Self {
-        Self::new("", 0.0, 0, 0)
+        Self::new("", Price::ZERO, 0, 0)
     }
 }
 
@@ -50,8 +230,48 @@ impl Display for StockQuote {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}|{}|{}|{}",
-            self.ticker, self.price, self.volume, self.timestamp
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            self.ticker,
+            self.price,
+            self.volume,
+            self.timestamp,
+            self.bid,
+            self.ask,
+            self.bid_size,
+            self.ask_size,
+            self.last_size
         )
     }
 }
+
+/// Quote tagged with its position in a streaming session
+///
+/// Sequence numbers start at 1 and grow by one for every quote sent in the session,
+/// so receivers can detect lost, duplicated and reordered datagrams
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequencedQuote {
+    pub seq: u64,
+    pub quote: StockQuote,
+}
+
+impl SequencedQuote {
+    /// Creates a new SequencedQuote
+    pub fn new(seq: u64, quote: StockQuote) -> Self {
+        Self { seq, quote }
+    }
+
+    /// Parses the `seq|ticker|price|...` text format produced by `Display`
+    pub fn from_string(s: &str) -> Option<Self> {
+        let (seq, quote) = s.split_once('|')?;
+        Some(Self {
+            seq: seq.parse().ok()?,
+            quote: StockQuote::from_string(quote)?,
+        })
+    }
+}
+
+impl Display for SequencedQuote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}|{}", self.seq, self.quote)
+    }
+}
@@ -0,0 +1,182 @@
+use std::io::{self, Read};
+
+use crate::core::types::SequencedQuote;
+
+/// First bytes of every frame
+pub const WIRE_MAGIC: [u8; 2] = *b"QS";
+/// Version of the frame layout and message payloads, bumped on incompatible changes
+pub const WIRE_VERSION: u8 = 1;
+/// Magic, version, message type and little-endian `u32` payload length
+pub const WIRE_HEADER_LEN: usize = 8;
+
+const TYPE_PING: u8 = 1;
+const TYPE_PONG: u8 = 2;
+const TYPE_QUOTE: u8 = 3;
+const TYPE_BATCH: u8 = 4;
+/// Bincode length prefix of the quote list in a batch payload
+const BATCH_LEN_PREFIX: usize = 8;
+/// Largest payload accepted from a byte stream, anything above means corrupted or foreign data
+const MAX_STREAM_PAYLOAD_LEN: usize = 1 << 20;
+
+/// Message exchanged between the streamer and its clients
+///
+/// Every message travels in a frame: `WIRE_MAGIC`, `WIRE_VERSION`, a message type byte
+/// and the payload length, followed by the bincode payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireMessage {
+    /// Keep-alive sent by the client
+    Ping,
+    /// Answer to a ping
+    Pong,
+    /// One quote of the stream
+    Quote(SequencedQuote),
+    /// Several consecutive quotes of the stream packed into one frame
+    Batch(Vec<SequencedQuote>),
+}
+
+impl WireMessage {
+    /// Encodes the message into a frame
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let (message_type, payload) = match self {
+            WireMessage::Ping => (TYPE_PING, Vec::new()),
+            WireMessage::Pong => (TYPE_PONG, Vec::new()),
+            WireMessage::Quote(quote) => (TYPE_QUOTE, bincode::serialize(quote).map_err(io::Error::other)?),
+            WireMessage::Batch(quotes) => (TYPE_BATCH, bincode::serialize(quotes).map_err(io::Error::other)?),
+        };
+
+        let mut frame = Vec::with_capacity(WIRE_HEADER_LEN + payload.len());
+        frame.extend_from_slice(&WIRE_MAGIC);
+        frame.push(WIRE_VERSION);
+        frame.push(message_type);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        Ok(frame)
+    }
+
+    /// Reads the next frame from a byte stream such as a TCP connection, None at a clean end of stream
+    ///
+    /// Frames follow each other without separator, the header tells where each one ends
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut frame = vec![0u8; WIRE_HEADER_LEN];
+        // Конец потока допустим только между кадрами
+        let mut filled = 0;
+        while filled < WIRE_HEADER_LEN {
+            match reader.read(&mut frame[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if frame[..2] != WIRE_MAGIC {
+            return Err(invalid("not a quote stream frame".to_string()));
+        }
+        let len = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]) as usize;
+        if len > MAX_STREAM_PAYLOAD_LEN {
+            return Err(invalid(format!("payload of {} bytes exceeds {}", len, MAX_STREAM_PAYLOAD_LEN)));
+        }
+
+        frame.resize(WIRE_HEADER_LEN + len, 0);
+        reader.read_exact(&mut frame[WIRE_HEADER_LEN..])?;
+        Self::decode(&frame).map(Some)
+    }
+
+    /// Decodes a frame, rejecting foreign data, other protocol versions and unknown message types
+    pub fn decode(frame: &[u8]) -> io::Result<Self> {
+        if frame.len() < WIRE_HEADER_LEN {
+            return Err(invalid(format!("frame of {} bytes is shorter than the header", frame.len())));
+        }
+        if frame[..2] != WIRE_MAGIC {
+            return Err(invalid("not a quote stream frame".to_string()));
+        }
+        if frame[2] != WIRE_VERSION {
+            return Err(invalid(format!(
+                "unsupported protocol version {}, expected {}",
+                frame[2], WIRE_VERSION
+            )));
+        }
+
+        let len = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]) as usize;
+        let payload = &frame[WIRE_HEADER_LEN..];
+        if payload.len() != len {
+            return Err(invalid(format!(
+                "payload of {} bytes, header announces {}",
+                payload.len(),
+                len
+            )));
+        }
+
+        match frame[3] {
+            TYPE_PING => Ok(WireMessage::Ping),
+            TYPE_PONG => Ok(WireMessage::Pong),
+            TYPE_QUOTE => bincode::deserialize(payload)
+                .map(WireMessage::Quote)
+                .map_err(|e| invalid(format!("invalid quote payload: {}", e))),
+            TYPE_BATCH => bincode::deserialize(payload)
+                .map(WireMessage::Batch)
+                .map_err(|e| invalid(format!("invalid batch payload: {}", e))),
+            other => Err(invalid(format!("unknown message type {}", other))),
+        }
+    }
+}
+
+/// Packs consecutive quotes into frames of at most `max_frame_len` bytes
+///
+/// A quote that does not fit in the pending frame closes it. A single quote larger
+/// than the limit still gets a frame of its own.
+pub struct QuoteBatcher {
+    max_frame_len: usize,
+    quotes: Vec<SequencedQuote>,
+    frame_len: usize,
+}
+
+impl QuoteBatcher {
+    /// Creates a new QuoteBatcher
+    pub fn new(max_frame_len: usize) -> Self {
+        Self {
+            max_frame_len,
+            quotes: Vec::new(),
+            frame_len: WIRE_HEADER_LEN + BATCH_LEN_PREFIX,
+        }
+    }
+
+    /// Adds a quote, returning the pending frame first if the quote does not fit in it
+    pub fn push(&mut self, quote: SequencedQuote) -> io::Result<Option<Vec<u8>>> {
+        let quote_len = bincode::serialized_size(&quote).map_err(io::Error::other)? as usize;
+        let full = if !self.quotes.is_empty() && self.frame_len + quote_len > self.max_frame_len {
+            self.flush()?
+        } else {
+            None
+        };
+
+        self.frame_len += quote_len;
+        self.quotes.push(quote);
+        Ok(full)
+    }
+
+    /// Returns the frame of the pending quotes, None if there are none
+    ///
+    /// A lone quote is sent as a `Quote` message, several as a `Batch`
+    pub fn flush(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut quotes = std::mem::take(&mut self.quotes);
+        self.frame_len = WIRE_HEADER_LEN + BATCH_LEN_PREFIX;
+
+        let message = match quotes.len() {
+            0 => return Ok(None),
+            1 => WireMessage::Quote(quotes.remove(0)),
+            _ => WireMessage::Batch(quotes),
+        };
+        message.encode().map(Some)
+    }
+
+    /// Returns true if no quote is waiting to be sent
+    pub fn is_empty(&self) -> bool {
+        self.quotes.is_empty()
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
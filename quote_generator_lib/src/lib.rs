@@ -1,9 +1,19 @@
 pub mod core {
+    mod price;
+    mod price_model;
     mod quote_generator;
+    mod quote_source;
+    mod recording;
     mod types;
+    mod wire;
 
-    pub use self::quote_generator::QuoteGenerator;
-    pub use self::types::StockQuote;
+    pub use self::price::Price;
+    pub use self::price_model::{GbmModel, GbmParams, PriceModel};
+    pub use self::quote_generator::{Clock, QuoteGenerator};
+    pub use self::quote_source::{ChainedSource, GeneratorSource, MergedSource, QuoteSource, ReplaySource};
+    pub use self::recording::{QuoteReader, RecordFormat, ReplaySpeed, write_quote};
+    pub use self::types::{InstrumentSpec, LiquidityTier, SequencedQuote, StockQuote};
+    pub use self::wire::{QuoteBatcher, WIRE_HEADER_LEN, WIRE_MAGIC, WIRE_VERSION, WireMessage};
 }
 
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -24,14 +34,23 @@ pub fn timestamp() -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::core::{QuoteGenerator, StockQuote};
+    use super::core::{
+        ChainedSource, GbmModel, GbmParams, GeneratorSource, InstrumentSpec, LiquidityTier, MergedSource, Price,
+        PriceModel, QuoteGenerator, QuoteReader, QuoteSource, RecordFormat, ReplaySource, ReplaySpeed, SequencedQuote,
+        QuoteBatcher, StockQuote, WIRE_VERSION, WireMessage, write_quote,
+    };
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
     use super::*;
 
     #[test]
     fn quote_to_string() {
         let timestamp = get_current_timestamp();
         let quote = StockQuote::new("AAPL", 123.4, 1000, timestamp);
-        assert_eq!(quote.to_string(), format!("AAPL|123.4|1000|{}", timestamp));
+        assert_eq!(quote.to_string(), format!("AAPL|123.4|1000|{}|123.4|123.4|0|0|0", timestamp));
+
+        let quote = quote.with_book(123.3, 123.5, 200, 300).with_last_size(10);
+        assert_eq!(quote.to_string(), format!("AAPL|123.4|1000|{}|123.3|123.5|200|300|10", timestamp));
     }
 
     #[test]
@@ -41,6 +60,20 @@ mod tests {
         assert_eq!(quote.price, 123.4);
         assert_eq!(quote.volume, 1000);
         assert_eq!(quote.timestamp, 1234567890);
+        assert_eq!(quote.bid, 123.4);
+        assert_eq!(quote.ask, 123.4);
+    }
+
+    #[test]
+    fn quote_from_string_with_book() {
+        let quote = StockQuote::from_string("AAPL|123.5|1000|1234567890|123.3|123.5|200|300|10").unwrap();
+        assert_eq!(quote.price, 123.5);
+        assert_eq!(quote.bid, 123.3);
+        assert_eq!(quote.ask, 123.5);
+        assert_eq!(quote.bid_size, 200);
+        assert_eq!(quote.ask_size, 300);
+        assert_eq!(quote.last_size, 10);
+        assert_eq!(StockQuote::from_string(&quote.to_string()), Some(quote));
     }
 
     #[test]
@@ -49,6 +82,14 @@ mod tests {
         assert!(StockQuote::from_string("AAPL|invalid|1000|123").is_none());
     }
 
+    #[test]
+    fn sequenced_quote_text_roundtrip() {
+        let sequenced = SequencedQuote::new(42, StockQuote::new("AAPL", 123.4, 1000, 1234567890));
+        assert_eq!(sequenced.to_string(), "42|AAPL|123.4|1000|1234567890|123.4|123.4|0|0|0");
+        assert_eq!(SequencedQuote::from_string(&sequenced.to_string()), Some(sequenced));
+        assert!(SequencedQuote::from_string("AAPL|123.4|1000|1234567890").is_none());
+    }
+
     #[test]
     fn quote_generator_new() {
         let generator = QuoteGenerator::new();
@@ -57,13 +98,313 @@ mod tests {
 
     #[test]
     fn quote_generator_generate() {
-        let mut generator = QuoteGenerator::new();
-        let quote1 = generator.generate_quote("AAPL").unwrap();
-        assert_eq!(quote1.ticker, "AAPL");
-        assert!(quote1.price > 0.0);
-        
-        let quote2 = generator.generate_quote("AAPL").unwrap();
-        assert_ne!(quote1.price, quote2.price);
+        let mut generator = QuoteGenerator::from_seed(7);
+        let prices: Vec<String> = (0..5)
+            .map(|_| generator.generate_quote("AAPL").unwrap())
+            .inspect(|quote| assert_eq!(quote.ticker, "AAPL"))
+            .map(|quote| quote.price.to_string())
+            .collect();
+        // Цены округлены до тика, поэтому последовательность от зерна точная
+        assert_eq!(prices, ["115.76", "115.7", "115.67", "115.57", "115.46"]);
+    }
+
+    fn seeded_generator(seed: u64) -> QuoteGenerator {
+        let mut ticks = 0;
+        QuoteGenerator::from_seed(seed).with_clock(Box::new(move || {
+            ticks += 1;
+            1_700_000_000_000 + ticks
+        }))
+    }
+
+    fn generate_series(generator: &mut QuoteGenerator) -> Vec<StockQuote> {
+        (0..50)
+            .flat_map(|_| ["AAPL", "MSFT", "ZTS"])
+            .filter_map(|ticker| generator.generate_quote(ticker))
+            .collect()
+    }
+
+    #[test]
+    fn quote_generator_book_is_consistent() {
+        let mut generator = QuoteGenerator::from_seed(7);
+        for quote in generate_series(&mut generator) {
+            assert!(quote.ask > quote.bid);
+            assert!(quote.price == quote.bid || quote.price == quote.ask);
+            assert!(quote.bid_size > 0 && quote.ask_size > 0 && quote.last_size > 0);
+        }
+    }
+
+    #[test]
+    fn quote_generator_spread_scales_with_tier() {
+        let mut generator = QuoteGenerator::from_seed(11);
+        let relative_spread = |generator: &mut QuoteGenerator, ticker: &str| {
+            let spreads: Vec<f64> = (0..200)
+                .filter_map(|_| generator.generate_quote(ticker))
+                .map(|q| q.spread().to_f64() / q.mid())
+                .collect();
+            spreads.iter().sum::<f64>() / spreads.len() as f64
+        };
+
+        let liquid = relative_spread(&mut generator, "AAPL");
+        let illiquid = relative_spread(&mut generator, "ZTS");
+        assert_eq!(LiquidityTier::for_ticker("AAPL"), LiquidityTier::High);
+        assert_eq!(LiquidityTier::for_ticker("ZTS"), LiquidityTier::Low);
+        assert!(illiquid > liquid);
+    }
+
+    #[test]
+    fn quote_generator_respects_tick_size() {
+        let nickel = InstrumentSpec::new("0.05".parse().unwrap(), 2).unwrap();
+        let mut generator = QuoteGenerator::from_seed(3).with_instrument("BKNG", nickel);
+        for _ in 0..100 {
+            let quote = generator.generate_quote("BKNG").unwrap();
+            for price in [quote.price, quote.bid, quote.ask] {
+                assert_eq!(price.raw() % nickel.tick_size().raw(), 0);
+            }
+            assert!(quote.spread() >= nickel.tick_size());
+
+            let quote = generator.generate_quote("AAPL").unwrap();
+            assert_eq!(quote.bid.raw() % InstrumentSpec::US_EQUITY.tick_size().raw(), 0);
+        }
+    }
+
+    #[test]
+    fn quote_generator_uses_instrument_profile() {
+        let start: Price = "12.34".parse().unwrap();
+        let mut generator = QuoteGenerator::from_seed(5)
+            .with_start_price("PENNY", start)
+            .with_tier("PENNY", LiquidityTier::High);
+        let quote = generator.generate_quote("PENNY").unwrap();
+        assert!((quote.mid() - start.to_f64()).abs() < 0.05);
+        assert!(quote.volume >= 1000);
+        assert_eq!("Medium".parse::<LiquidityTier>(), Ok(LiquidityTier::Medium));
+        assert!("huge".parse::<LiquidityTier>().is_err());
+    }
+
+    #[test]
+    fn price_parse_and_display() {
+        let price: Price = "183.13".parse().unwrap();
+        assert_eq!(price.raw(), 1_831_300);
+        assert_eq!(price.to_string(), "183.13");
+        assert_eq!(price.format(4), "183.1300");
+        assert_eq!(format!("{:>8}|{:<8}|", price, price), "  183.13|183.13  |");
+        assert_eq!(price.decimals(), 2);
+        assert_eq!("100".parse::<Price>().unwrap().decimals(), 0);
+        assert_eq!("-0.0005".parse::<Price>().unwrap().decimals(), 4);
+        assert_eq!(Price::from_raw(5).to_string(), "0.0005");
+        assert_eq!("-2.5".parse::<Price>().unwrap().to_string(), "-2.5");
+        assert_eq!("100".parse::<Price>().unwrap().to_string(), "100");
+        assert!("1.23456".parse::<Price>().is_err());
+        assert!("abc".parse::<Price>().is_err());
+        assert!(".5".parse::<Price>().is_err());
+        assert!("1.".parse::<Price>().is_err());
+        assert!("-1.".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn price_tick_rounding() {
+        let tick: Price = "0.05".parse().unwrap();
+        let price: Price = "10.03".parse().unwrap();
+        assert_eq!(price.floor_to_tick(tick).to_string(), "10");
+        assert_eq!(price.ceil_to_tick(tick).to_string(), "10.05");
+        assert_eq!(price.round_to_tick(tick).to_string(), "10.05");
+        assert_eq!(Price::from_f64(183.1325004178757).to_string(), "183.1325");
+    }
+
+    #[test]
+    fn instrument_spec_validation() {
+        assert!(InstrumentSpec::new("0.01".parse().unwrap(), 2).is_ok());
+        assert!(InstrumentSpec::new("0.005".parse().unwrap(), 2).is_err());
+        assert!(InstrumentSpec::new(Price::ZERO, 2).is_err());
+        assert!(InstrumentSpec::new("0.01".parse().unwrap(), 5).is_err());
+        assert_eq!(InstrumentSpec::US_EQUITY.format_price("7.5".parse().unwrap()), "7.50");
+        assert_eq!(InstrumentSpec::US_EQUITY.tick_size().to_string(), "0.01");
+        assert_eq!(InstrumentSpec::US_EQUITY.precision(), 2);
+    }
+
+    #[test]
+    fn instrument_spec_deserialize_validates() {
+        let spec: InstrumentSpec = serde_json::from_str(r#"{"tick_size": 0.05, "precision": 2}"#).unwrap();
+        assert_eq!(spec, InstrumentSpec::new("0.05".parse().unwrap(), 2).unwrap());
+        // Нулевой тик привёл бы к делению на ноль при округлении цен
+        assert!(serde_json::from_str::<InstrumentSpec>(r#"{"tick_size": 0, "precision": 2}"#).is_err());
+        assert!(serde_json::from_str::<InstrumentSpec>(r#"{"tick_size": 0.005, "precision": 2}"#).is_err());
+    }
+
+    #[test]
+    fn price_format_rounds() {
+        let price: Price = "183.1351".parse().unwrap();
+        assert_eq!(price.format(2), "183.14");
+        assert_eq!(price.format(0), "183");
+        assert_eq!("9.995".parse::<Price>().unwrap().format(2), "10.00");
+        assert_eq!("-2.345".parse::<Price>().unwrap().format(2), "-2.35");
+        assert_eq!("-0.004".parse::<Price>().unwrap().format(2), "0.00");
+        assert_eq!(price.format(6), "183.135100");
+    }
+
+    #[test]
+    fn quote_generator_same_seed_same_quotes() {
+        let first = generate_series(&mut seeded_generator(42));
+        let second = generate_series(&mut seeded_generator(42));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn quote_generator_different_seed_different_quotes() {
+        let first = generate_series(&mut seeded_generator(1));
+        let second = generate_series(&mut seeded_generator(2));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn quote_generator_with_custom_rng() {
+        let rng = rand::rngs::mock::StepRng::new(0, 1 << 60);
+        let mut generator = QuoteGenerator::from_rng(Box::new(rng));
+        let quote = generator.generate_quote("ZTS").unwrap();
+        assert!(quote.mid() >= 100.0 && quote.mid() < 200.0);
+    }
+
+    #[test]
+    fn gbm_model_never_crosses_zero() {
+        let mut rng = rand::thread_rng();
+        let mut model = GbmModel::new().with_default_params(GbmParams::new(-5.0, 3.0));
+        let mut price = 1.0;
+        for _ in 0..10_000 {
+            price = model.next_price(&mut rng, "PENNY", price);
+            assert!(price > 0.0);
+        }
+    }
+
+    #[test]
+    fn gbm_model_returns_scale_with_price() {
+        // Zero volatility leaves only the drift, so the return is the same percentage at any level
+        let params = GbmParams::new(0.5, 0.0);
+        let mut model = GbmModel::new().with_dt(1.0).with_default_params(params);
+        let mut rng = rand::thread_rng();
+        let cheap = model.next_price(&mut rng, "CHEAP", 10.0);
+        let pricey = model.next_price(&mut rng, "PRICEY", 1000.0);
+        assert!((cheap / 10.0 - pricey / 1000.0).abs() < 1e-12);
+        assert!((cheap / 10.0 - 0.5f64.exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gbm_model_per_ticker_params() {
+        let tsla = GbmParams::new(0.1, 0.6);
+        let model = GbmModel::new().with_params("TSLA", tsla);
+        assert_eq!(model.params("TSLA"), tsla);
+        assert_eq!(model.params("AAPL"), GbmParams::default());
+    }
+
+    #[test]
+    fn recorded_quotes_round_trip() {
+        let quotes = generate_series(&mut seeded_generator(9));
+        for format in [RecordFormat::Text, RecordFormat::Binary] {
+            let mut buffer = Vec::new();
+            for quote in &quotes {
+                write_quote(&mut buffer, format, quote).unwrap();
+            }
+            let replayed: Vec<StockQuote> = QuoteReader::new(Cursor::new(buffer), format)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(replayed, quotes);
+        }
+    }
+
+    #[test]
+    fn recorded_text_skips_comments_and_reports_bad_lines() {
+        let text = "# ticker|price|volume|timestamp\n\nAAPL|123.4|1000|1\nbroken\n";
+        let mut reader = QuoteReader::new(Cursor::new(text), RecordFormat::Text);
+        assert_eq!(reader.next().unwrap().unwrap().ticker, "AAPL");
+        let error = reader.next().unwrap().unwrap_err();
+        assert!(error.to_string().contains("line 4"));
+    }
+
+    #[test]
+    fn recorded_binary_truncated() {
+        let mut buffer = Vec::new();
+        write_quote(&mut buffer, RecordFormat::Binary, &StockQuote::new("AAPL", 1.0, 1, 1)).unwrap();
+        buffer.pop();
+        let mut reader = QuoteReader::new(Cursor::new(buffer), RecordFormat::Binary);
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn replay_speed_parse_and_pacing() {
+        assert_eq!("max".parse(), Ok(ReplaySpeed::Max));
+        assert_eq!("original".parse(), Ok(ReplaySpeed::Multiplier(1.0)));
+        assert_eq!("10x".parse(), Ok(ReplaySpeed::Multiplier(10.0)));
+        assert_eq!("0.5".parse(), Ok(ReplaySpeed::Multiplier(0.5)));
+        assert!("0x".parse::<ReplaySpeed>().is_err());
+        assert!("fast".parse::<ReplaySpeed>().is_err());
+
+        assert_eq!(ReplaySpeed::Multiplier(1.0).due_after(1500), Duration::from_millis(1500));
+        assert_eq!(ReplaySpeed::Multiplier(10.0).due_after(1500), Duration::from_millis(150));
+        assert_eq!(ReplaySpeed::Max.due_after(1500), Duration::ZERO);
+        assert_eq!(RecordFormat::from_path(std::path::Path::new("day.BIN")), RecordFormat::Binary);
+        assert_eq!(RecordFormat::from_path(std::path::Path::new("day.csv")), RecordFormat::Text);
+    }
+
+    fn drain(source: &mut dyn QuoteSource) -> Vec<StockQuote> {
+        std::iter::from_fn(|| source.next_quote()).map(|q| q.unwrap()).collect()
+    }
+
+    fn replay_source(name: &str, timestamps: &[u64], speed: ReplaySpeed) -> ReplaySource {
+        let quotes: Vec<std::io::Result<StockQuote>> = timestamps
+            .iter()
+            .map(|&ts| Ok(StockQuote::new(name, 1.0, 1, ts)))
+            .collect();
+        ReplaySource::new(name, quotes.into_iter(), speed)
+    }
+
+    #[test]
+    fn generator_source_cycles_tickers() {
+        let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+        let mut source = GeneratorSource::new(QuoteGenerator::from_seed(1), tickers);
+        let tickers: Vec<String> = (0..5)
+            .map(|_| source.next_quote().unwrap().unwrap().ticker)
+            .collect();
+        assert_eq!(tickers, ["AAPL", "MSFT", "AAPL", "MSFT", "AAPL"]);
+
+        let mut empty = GeneratorSource::new(QuoteGenerator::new(), Vec::new());
+        assert!(empty.next_quote().is_none());
+    }
+
+    #[test]
+    fn replay_source_paces_quotes() {
+        let mut source = replay_source("AAPL", &[1_000, 1_100, 1_200], ReplaySpeed::Multiplier(2.0));
+        let started = Instant::now();
+        assert_eq!(drain(&mut source).len(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn replay_source_stops_after_error() {
+        let quotes = vec![
+            Ok(StockQuote::new("AAPL", 1.0, 1, 1)),
+            Err(std::io::Error::other("broken")),
+            Ok(StockQuote::new("AAPL", 1.0, 1, 2)),
+        ];
+        let mut source = ReplaySource::new("broken", quotes.into_iter(), ReplaySpeed::Max);
+        assert!(source.next_quote().unwrap().is_ok());
+        assert!(source.next_quote().unwrap().is_err());
+        assert!(source.next_quote().is_none());
+    }
+
+    #[test]
+    fn merged_and_chained_sources() {
+        let mut merged = MergedSource::new(vec![
+            Box::new(replay_source("AAPL", &[1, 2, 3], ReplaySpeed::Max)),
+            Box::new(replay_source("MSFT", &[1, 2], ReplaySpeed::Max)),
+        ]);
+        let mut tickers: Vec<String> = drain(&mut merged).into_iter().map(|q| q.ticker).collect();
+        tickers.sort();
+        assert_eq!(tickers, ["AAPL", "AAPL", "AAPL", "MSFT", "MSFT"]);
+
+        let mut chained = ChainedSource::new(vec![
+            Box::new(replay_source("AAPL", &[1, 2], ReplaySpeed::Max)),
+            Box::new(replay_source("MSFT", &[1], ReplaySpeed::Max)),
+        ]);
+        let tickers: Vec<String> = drain(&mut chained).into_iter().map(|q| q.ticker).collect();
+        assert_eq!(tickers, ["AAPL", "AAPL", "MSFT"]);
     }
 
     #[test]
@@ -93,4 +434,91 @@ mod tests {
         assert_eq!(time_parts[1].len(), 2); // Minute
         assert_eq!(time_parts[2].len(), 2); // Second
     }
+
+    #[test]
+    fn wire_messages_roundtrip() {
+        let quote = SequencedQuote::new(7, StockQuote::new("AAPL", 183.15, 100, 1).with_book(183.1, 183.15, 200, 300));
+        let batch = WireMessage::Batch(vec![quote.clone(), SequencedQuote::new(8, StockQuote::new("MSFT", 410.5, 200, 2))]);
+        for message in [WireMessage::Ping, WireMessage::Pong, WireMessage::Quote(quote), batch] {
+            let frame = message.encode().unwrap();
+            assert_eq!(&frame[..2], b"QS");
+            assert_eq!(frame[2], WIRE_VERSION);
+            assert_eq!(WireMessage::decode(&frame).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn wire_rejects_invalid_frames() {
+        let frame = WireMessage::Quote(SequencedQuote::new(1, StockQuote::new("AAPL", 183.15, 100, 1)))
+            .encode()
+            .unwrap();
+
+        assert!(WireMessage::decode(b"ping").is_err());
+        assert!(WireMessage::decode(&frame[..frame.len() - 1]).is_err());
+
+        let mut other_version = frame.clone();
+        other_version[2] = WIRE_VERSION + 1;
+        let error = WireMessage::decode(&other_version).unwrap_err();
+        assert!(error.to_string().contains("unsupported protocol version"));
+
+        let mut unknown_type = frame.clone();
+        unknown_type[3] = 200;
+        assert!(WireMessage::decode(&unknown_type).is_err());
+
+        let mut foreign = frame;
+        foreign[0] = b'X';
+        assert!(WireMessage::decode(&foreign).is_err());
+    }
+
+    #[test]
+    fn batcher_respects_frame_size() {
+        let quote = |seq| SequencedQuote::new(seq, StockQuote::new("AAPL", 183.15, 100, seq));
+        let mut batcher = QuoteBatcher::new(200);
+        let mut frames = Vec::new();
+        for seq in 1..=10 {
+            frames.extend(batcher.push(quote(seq)).unwrap());
+        }
+        frames.extend(batcher.flush().unwrap());
+        assert!(batcher.is_empty());
+        assert_eq!(batcher.flush().unwrap(), None);
+
+        let mut seqs = Vec::new();
+        for frame in &frames {
+            assert!(frame.len() <= 200);
+            match WireMessage::decode(frame).unwrap() {
+                WireMessage::Batch(quotes) => seqs.extend(quotes.iter().map(|q| q.seq)),
+                WireMessage::Quote(quote) => seqs.push(quote.seq),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        assert!(frames.len() > 1 && frames.len() < 10);
+        assert_eq!(seqs, (1..=10).collect::<Vec<_>>());
+
+        // A quote larger than the limit still goes out on its own
+        let mut batcher = QuoteBatcher::new(10);
+        assert_eq!(batcher.push(quote(1)).unwrap(), None);
+        assert!(batcher.push(quote(2)).unwrap().is_some());
+        assert!(matches!(
+            WireMessage::decode(&batcher.flush().unwrap().unwrap()).unwrap(),
+            WireMessage::Quote(_)
+        ));
+    }
+
+    #[test]
+    fn wire_frames_read_from_stream() {
+        let quote = SequencedQuote::new(1, StockQuote::new("AAPL", 183.15, 100, 1));
+        let mut stream = Vec::new();
+        stream.extend(WireMessage::Quote(quote.clone()).encode().unwrap());
+        stream.extend(WireMessage::Pong.encode().unwrap());
+
+        let mut reader = Cursor::new(stream.clone());
+        assert_eq!(WireMessage::read_from(&mut reader).unwrap(), Some(WireMessage::Quote(quote)));
+        assert_eq!(WireMessage::read_from(&mut reader).unwrap(), Some(WireMessage::Pong));
+        assert_eq!(WireMessage::read_from(&mut reader).unwrap(), None);
+
+        // A stream cut in the middle of a frame is an error, not a clean end
+        let mut reader = Cursor::new(stream[..stream.len() - 12].to_vec());
+        assert!(WireMessage::read_from(&mut reader).is_err());
+        assert!(WireMessage::read_from(&mut Cursor::new(b"QS\x01\x03\xff\xff\xff\xff".to_vec())).is_err());
+    }
 }
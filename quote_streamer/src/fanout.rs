@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use crossbeam_queue::ArrayQueue;
+use crossbeam_utils::sync::{Parker, Unparker};
+
+use quote_generator_lib::core::StockQuote;
+
+/// What a subscriber queue does with a new quote when it is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued quote to make room
+    #[default]
+    DropOldest,
+    /// Replace the newest queued quote of the same ticker, or drop the oldest one when there is none
+    ConflatePerTicker,
+    /// Disconnect the subscriber, ending its stream
+    Disconnect,
+}
+
+/// Bounded queue of one subscriber, filled by the producer and drained by the subscriber thread
+///
+/// A lock-free ring buffer: neither side ever waits for the other
+struct Queue {
+    quotes: ArrayQueue<StockQuote>,
+    policy: OverflowPolicy,
+    /// Set when the subscriber is gone, was disconnected by the policy or the fan-out was dropped
+    closed: AtomicBool,
+    /// Set when the `Disconnect` policy closed the queue
+    evicted: AtomicBool,
+    overflows: AtomicU64,
+    /// Wakes the subscriber waiting for a quote
+    unparker: Unparker,
+}
+
+impl Queue {
+    /// Queues a quote applying the overflow policy, returns false once the queue is closed
+    fn push(&self, quote: &StockQuote) -> bool {
+        if self.closed.load(Ordering::Acquire) {
+            return false;
+        }
+
+        if let Err(quote) = self.quotes.push(quote.clone()) {
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    self.quotes.force_push(quote);
+                }
+                OverflowPolicy::ConflatePerTicker => self.conflate(quote),
+                OverflowPolicy::Disconnect => {
+                    self.evicted.store(true, Ordering::Relaxed);
+                    self.close();
+                    while self.quotes.pop().is_some() {}
+                    return false;
+                }
+            }
+        }
+        self.unparker.unpark();
+        true
+    }
+
+    /// Replaces the newest queued quote of the same ticker, or drops the oldest one when there is none
+    ///
+    /// Only the producer calls it: the queued quotes are taken out and put back in the same order,
+    /// the subscriber keeps popping from the front meanwhile
+    fn conflate(&self, quote: StockQuote) {
+        let mut queued: Vec<StockQuote> = std::iter::from_fn(|| self.quotes.pop()).collect();
+        // Новая котировка занимает место последней по тому же тикеру, чтобы порядок котировок тикера не нарушился
+        match queued.iter_mut().rev().find(|q| q.ticker == quote.ticker) {
+            Some(same) => *same = quote,
+            None => {
+                if queued.len() >= self.quotes.capacity() {
+                    queued.remove(0);
+                }
+                queued.push(quote);
+            }
+        }
+        for quote in queued {
+            self.quotes.force_push(quote);
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.unparker.unpark();
+    }
+}
+
+/// List of subscriber queues, shared with the subscribers so they can leave it when dropped
+type Subscribers = Mutex<Vec<Arc<Queue>>>;
+
+/// Single producer to many consumers distribution of quotes
+///
+/// Every subscriber has a bounded lock-free queue of its own, so publishing never waits for a consumer to catch up:
+/// a subscriber that falls behind loses quotes according to the overflow policy instead of
+/// holding up the producer and everybody else. The only lock publishing takes guards the subscriber list
+/// and the last value cache, consumers take it just to subscribe and unsubscribe.
+/// Quotes are expected from a single producer thread, the order of concurrent publishers is not kept.
+/// The last published quote of every ticker is kept,
+/// so a new subscriber does not have to wait for the next generation cycle to know the prices.
+pub struct FanOut {
+    subscribers: Arc<Subscribers>,
+    /// Last value cache, only changed while `subscribers` is locked
+    last_values: Mutex<BTreeMap<String, StockQuote>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl FanOut {
+    /// Creates a new FanOut giving every subscriber a queue of `capacity` quotes, overflowing by `policy` unless it asks otherwise
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            last_values: Mutex::new(BTreeMap::new()),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    /// Adds a subscriber receiving every quote published from now on
+    pub fn subscribe(&self) -> Subscriber {
+        self.subscribe_with_snapshot().1
+    }
+
+    /// Adds a subscriber whose queue overflows by `policy` instead of the policy of the fan-out
+    pub fn subscribe_with_policy(&self, policy: OverflowPolicy) -> Subscriber {
+        self.add_subscriber(policy).1
+    }
+
+    /// Adds a subscriber and returns the last quote of every ticker published before it, ordered by ticker
+    ///
+    /// Every quote is either in the snapshot or received by the subscriber, never in both
+    pub fn subscribe_with_snapshot(&self) -> (Vec<StockQuote>, Subscriber) {
+        self.add_subscriber(self.policy)
+    }
+
+    fn add_subscriber(&self, policy: OverflowPolicy) -> (Vec<StockQuote>, Subscriber) {
+        let parker = Parker::new();
+        let queue = Arc::new(Queue {
+            quotes: ArrayQueue::new(self.capacity),
+            policy,
+            closed: AtomicBool::new(false),
+            evicted: AtomicBool::new(false),
+            overflows: AtomicU64::new(0),
+            unparker: parker.unparker().clone(),
+        });
+        let mut snapshot = Vec::new();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            snapshot = self.snapshot();
+            subscribers.push(Arc::clone(&queue));
+        }
+        let subscriber = Subscriber {
+            queue,
+            parker,
+            subscribers: Arc::downgrade(&self.subscribers),
+        };
+        (snapshot, subscriber)
+    }
+
+    /// Returns the last published quote of every ticker, ordered by ticker
+    pub fn snapshot(&self) -> Vec<StockQuote> {
+        match self.last_values.lock() {
+            Ok(last_values) => last_values.values().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the number of subscribers receiving quotes
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().map(|subscribers| subscribers.len()).unwrap_or(0)
+    }
+
+    /// Hands the quote to every subscriber, forgetting the ones disconnected by the overflow policy
+    pub fn publish(&self, quote: StockQuote) {
+        // Список копируется под блокировкой, а в очереди котировка кладётся уже без неё
+        let queues = match self.subscribers.lock() {
+            Ok(subscribers) => {
+                if let Ok(mut last_values) = self.last_values.lock() {
+                    last_values.insert(quote.ticker.clone(), quote.clone());
+                }
+                subscribers.clone()
+            }
+            Err(_) => return,
+        };
+
+        let mut closed = false;
+        for queue in &queues {
+            closed |= !queue.push(&quote);
+        }
+        if closed && let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|queue| !queue.is_closed());
+        }
+    }
+}
+
+impl Drop for FanOut {
+    fn drop(&mut self) {
+        if let Ok(subscribers) = self.subscribers.lock() {
+            for queue in subscribers.iter() {
+                queue.close();
+            }
+        }
+    }
+}
+
+/// Receiving end of a FanOut subscription, unsubscribes when dropped
+pub struct Subscriber {
+    queue: Arc<Queue>,
+    parker: Parker,
+    subscribers: Weak<Subscribers>,
+}
+
+impl Subscriber {
+    /// Waits up to `timeout` for the next quote
+    ///
+    /// Returns `Disconnected` once the queue is closed and drained
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<StockQuote, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(quote) = self.queue.quotes.pop() {
+                return Ok(quote);
+            }
+            if self.queue.is_closed() {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            // Котировка, пришедшая до засыпания, будит сразу: её сигнал не теряется
+            self.parker.park_timeout(deadline - now);
+        }
+    }
+
+    /// Returns the next quote if one is queued
+    pub fn try_recv(&mut self) -> Result<StockQuote, TryRecvError> {
+        match self.queue.quotes.pop() {
+            Some(quote) => Ok(quote),
+            None if self.queue.is_closed() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Returns how many times the queue was full when a quote arrived
+    pub fn overflows(&self) -> u64 {
+        self.queue.overflows.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the subscriber fell behind and was disconnected by the `Disconnect` policy
+    pub fn is_evicted(&self) -> bool {
+        self.queue.evicted.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        self.queue.close();
+        while self.queue.quotes.pop().is_some() {}
+        // Очередь уходит из списка сразу, не дожидаясь следующей публикации
+        if let Some(subscribers) = self.subscribers.upgrade()
+            && let Ok(mut subscribers) = subscribers.lock()
+        {
+            subscribers.retain(|queue| !Arc::ptr_eq(queue, &self.queue));
+        }
+    }
+}
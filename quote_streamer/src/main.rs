@@ -1,27 +1,40 @@
 use clap::Parser;
 use log::{info, error};
-use std::net::TcpListener;
+use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::thread;
 
 use std::time::Duration;
 
-use bus::Bus;
-use quote_generator_lib::core::{QuoteGenerator, StockQuote};
+use quote_generator_lib::core::{
+    GbmModel, GbmParams, GeneratorSource, MergedSource, QuoteGenerator, QuoteReader, QuoteSource, ReplaySource,
+    ReplaySpeed,
+};
 
-use crate::server::handle_client;
+use crate::fanout::{FanOut, OverflowPolicy};
+use crate::multicast::{MulticastConfig, MulticastPublisher};
+use crate::quote_udp_sender::{BatchConfig, DEFAULT_BATCH_FLUSH_MS, DEFAULT_MAX_DATAGRAM_SIZE, StreamOptions};
+use crate::server::{ServerConfig, handle_client};
+use crate::tickers::{Instrument, Universe};
+use crate::ws_gateway::WsGateway;
 
+mod fanout;
+mod multicast;
+mod pacing;
+mod quote_tcp_sender;
 mod quote_udp_sender;
 mod server;
 mod tickers;
+mod ws_gateway;
 
 #[cfg(test)]
 mod tests;
 
 const QUOTE_GENERATION_INTERVAL_MS: u64 = 5000;
 const QUOTE_GENERATION_DELAY_MS: u64 = 10;
-const BUS_CAPACITY: usize = 10;
+/// Quotes a subscriber may fall behind by before the overflow policy applies
+const SUBSCRIBER_QUEUE_CAPACITY: usize = 1000;
 
 #[derive(Parser)]
 #[command(name = "quote_streamer")]
@@ -32,28 +45,122 @@ struct Cli {
 
     #[arg(short, long)]
     port: u16,
+
+    /// Ticker universe file (.csv, .toml or .json), the built-in universe is used when not given
+    #[arg(long)]
+    tickers_file: Option<PathBuf>,
+
+    /// Seed for the quote generator, the same seed reproduces the same price series
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Replay recorded quotes (pipe-delimited text or length-prefixed bincode `.bin`) instead of generating them,
+    /// repeat to merge several recordings
+    #[arg(long)]
+    replay: Vec<PathBuf>,
+
+    /// Replay pace: `original`, a multiplier such as `10x`, or `max` for as fast as possible
+    #[arg(long, default_value = "original", requires = "replay")]
+    replay_speed: ReplaySpeed,
+
+    /// Largest UDP datagram sent to clients, quotes are packed together up to this size
+    #[arg(long, default_value_t = DEFAULT_MAX_DATAGRAM_SIZE, value_parser = clap::value_parser!(u16).range(64..=65507))]
+    max_datagram_size: u16,
+
+    /// Longest time in milliseconds a quote waits for others to share its datagram, 0 disables batching
+    #[arg(long, default_value_t = DEFAULT_BATCH_FLUSH_MS)]
+    batch_flush_ms: u64,
+
+    /// Also publish all quotes to multicast groups starting at this address, e.g. 239.255.0.1:7000
+    #[arg(long)]
+    multicast: Option<SocketAddrV4>,
+
+    /// Number of multicast channels tickers are spread over, channel i uses the base group and port plus i
+    #[arg(long, default_value_t = 4, requires = "multicast")]
+    multicast_channels: u16,
+
+    /// Time to live of multicast datagrams, 1 keeps them on the local network
+    #[arg(long, default_value_t = 1, requires = "multicast")]
+    multicast_ttl: u32,
+
+    /// Local interface to publish multicast from, e.g. 127.0.0.1 for loopback only
+    #[arg(long, default_value = "0.0.0.0", requires = "multicast")]
+    multicast_interface: Ipv4Addr,
+
+    /// Also accept WebSocket clients on this port of the host, they subscribe with JSON requests and get quotes as JSON
+    #[arg(long)]
+    ws_port: Option<u16>,
+
+    /// What happens to a subscriber whose queue is full: `drop-oldest` quotes, `conflate-per-ticker` keeping
+    /// the newest quote of each ticker, or `disconnect` it
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::DropOldest)]
+    overflow_policy: OverflowPolicy,
+
+    /// Send a stream that fell behind only the newest quote of each ticker that piled up while its socket was busy
+    #[arg(long)]
+    conflate: bool,
 }
 
-fn streaming(tickers: Vec<String>, bus: Arc<Mutex<Bus<StockQuote>>>, interval_ms: u64) {
-    let mut generator = QuoteGenerator::new();
+/// Builds the random quote source for the ticker universe
+fn generator_source(instruments: Vec<Instrument>, seed: Option<u64>) -> GeneratorSource {
+    let mut generator = match seed {
+        Some(seed) => QuoteGenerator::from_seed(seed),
+        None => QuoteGenerator::new(),
+    };
+    let mut model = GbmModel::new();
+    for instrument in &instruments {
+        generator = generator
+            .with_instrument(&instrument.symbol, instrument.spec)
+            .with_tier(&instrument.symbol, instrument.tier);
+        if let Some(price) = instrument.start_price {
+            generator = generator.with_start_price(&instrument.symbol, price);
+        }
+        if let Some(volatility) = instrument.volatility {
+            let params = GbmParams::new(GbmParams::default().drift, volatility);
+            model = model.with_params(&instrument.symbol, params);
+        }
+    }
+    let generator = generator.with_model(Box::new(model));
+    let tickers: Vec<String> = instruments.into_iter().map(|i| i.symbol).collect();
 
-    thread::spawn(move || {
-        //let mut quote = StockQuote::new ("AAPL", 150.0, 1000, quote_generator_lib::get_current_timestamp());
+    GeneratorSource::new(generator, tickers)
+        .with_delay(Duration::from_millis(QUOTE_GENERATION_DELAY_MS))
+        .with_interval(Duration::from_millis(QUOTE_GENERATION_INTERVAL_MS))
+}
+
+/// Builds the replay source for the recorded files, merging them when there are several
+fn replay_source(paths: &[PathBuf], speed: ReplaySpeed) -> std::io::Result<Box<dyn QuoteSource>> {
+    let mut sources: Vec<Box<dyn QuoteSource>> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let reader = QuoteReader::open(path)?;
+        sources.push(Box::new(ReplaySource::new(&path.display().to_string(), reader, speed)));
+    }
+
+    if sources.len() == 1 {
+        Ok(sources.remove(0))
+    } else {
+        Ok(Box::new(MergedSource::new(sources)))
+    }
+}
 
-        loop {
-            for ticker in &tickers[..] {
+/// Publishes every quote of the source to the subscribers until the source is exhausted
+fn streaming(mut source: Box<dyn QuoteSource>, fanout: Arc<FanOut>) {
+    info!("Streaming quotes from {}", source.describe());
 
-                let quote = generator.generate_quote(ticker);
+    thread::spawn(move || {
+        let mut published = 0u64;
 
-                if let Some(quote) = quote {
-                    if let Ok(mut bus) = bus.lock() {
-                        bus.broadcast(quote.clone());
-                    }
-                    thread::sleep(Duration::from_millis(QUOTE_GENERATION_DELAY_MS));
+        while let Some(quote) = source.next_quote() {
+            match quote {
+                Ok(quote) => {
+                    // Не блокируется: отстающие подписчики теряют котировки по своей политике
+                    fanout.publish(quote);
+                    published += 1;
                 }
+                Err(e) => error!("Quote source {} failed: {}", source.describe(), e),
             }
-            thread::sleep(Duration::from_millis(interval_ms));
         }
+        info!("Quote source {} finished after {} quotes", source.describe(), published);
     });
 }
 
@@ -67,33 +174,84 @@ fn main() -> std::io::Result<()> {
         cli.host, cli.port
     );
 
-    let tickers = tickers::get_tickers();
+    let instruments = match &cli.tickers_file {
+        Some(path) => tickers::load_instruments(path).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Failed to load tickers file {}: {}", path.display(), e),
+            )
+        })?,
+        None => tickers::get_instruments(),
+    };
 
-    info!("Starting streaming for tickers: {:?}", tickers);
+    let universe = Arc::new(Universe::new(&instruments));
 
-    // Create internal bus for StockQuote streaming to the UDP clients in single producer -> multiple consumers mode
-    let bus: Arc<Mutex<Bus<StockQuote>>> = Arc::new(Mutex::new(Bus::new(BUS_CAPACITY)));
+    // Create internal fan-out for StockQuote streaming to the clients in single producer -> multiple consumers mode
+    let fanout = Arc::new(FanOut::new(SUBSCRIBER_QUEUE_CAPACITY, cli.overflow_policy));
 
-    let bus_clone0 = Arc::clone(&bus);
-    streaming(
-        tickers,
-        bus_clone0,
-        QUOTE_GENERATION_INTERVAL_MS,
-    );
+    let source: Box<dyn QuoteSource> = if cli.replay.is_empty() {
+        info!(
+            "Starting streaming for tickers: {:?}",
+            instruments.iter().map(|i| i.symbol.as_str()).collect::<Vec<_>>()
+        );
+        if let Some(seed) = cli.seed {
+            info!("Using deterministic quote generation with seed {}", seed);
+        }
+        Box::new(generator_source(instruments, cli.seed))
+    } else {
+        replay_source(&cli.replay, cli.replay_speed)?
+    };
+
+    let batching = BatchConfig {
+        max_datagram_size: cli.max_datagram_size as usize,
+        flush_interval: Duration::from_millis(cli.batch_flush_ms),
+    };
+
+    let multicast = match cli.multicast {
+        Some(group) => {
+            let config = MulticastConfig::new(group, cli.multicast_channels, cli.multicast_ttl, cli.multicast_interface)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            MulticastPublisher::new(config, batching)?
+                .start(Arc::clone(&fanout))
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            Some(config)
+        }
+        None => None,
+    };
+    let stream = StreamOptions {
+        batching,
+        conflate: cli.conflate,
+        ..StreamOptions::default()
+    };
+    let config = ServerConfig {
+        stream,
+        multicast,
+        universe,
+    };
+
+    if let Some(ws_port) = cli.ws_port {
+        WsGateway::bind(format!("{}:{}", cli.host, ws_port).parse().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid WebSocket address: {}", e))
+        })?)?
+        .start(Arc::clone(&fanout))?;
+    }
+
+    // Читатель мультикаста подключается к шине до первой котировки
+    let fanout_clone0 = Arc::clone(&fanout);
+    streaming(source, fanout_clone0);
 
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                let bus_clone1 = Arc::clone(&bus);
+                let fanout_clone1 = Arc::clone(&fanout);
+                let config = config.clone();
                 thread::spawn(move || {
-                    handle_client(stream, bus_clone1);
+                    handle_client(stream, fanout_clone1, config);
                 });
             }
             Err(e) => error!("Connection failed: {}", e),
         }
     }
 
-    //drop(bus);
-
     Ok(())
 }
@@ -0,0 +1,148 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use quote_generator_lib::timestamp;
+
+use crate::fanout::{FanOut, OverflowPolicy};
+use crate::quote_udp_sender::{BatchConfig, FrameQueue};
+
+/// Longest wait for a quote while no batch is pending
+const IDLE_WAIT_MS: u64 = 100;
+
+/// Multicast publishing settings
+///
+/// Tickers are spread over `channels` groups: channel `i` is published to the base group
+/// address plus `i` on the base port plus `i`, so every channel can be joined on its own socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastConfig {
+    pub group: SocketAddrV4,
+    pub channels: u16,
+    pub ttl: u32,
+    /// Local interface multicast traffic is sent from, `0.0.0.0` lets the OS pick it
+    pub interface: Ipv4Addr,
+}
+
+impl MulticastConfig {
+    /// Checks the base group and the channel count
+    pub fn new(group: SocketAddrV4, channels: u16, ttl: u32, interface: Ipv4Addr) -> Result<Self, String> {
+        if !group.ip().is_multicast() {
+            return Err(format!("{} is not a multicast address", group.ip()));
+        }
+        if channels == 0 {
+            return Err("at least one multicast channel is needed".to_string());
+        }
+        let last = Self { group, channels, ttl, interface }.channel_addr(channels - 1);
+        if !last.ip().is_multicast() || last.port() < group.port() {
+            return Err(format!("{} channels do not fit after {}", channels, group));
+        }
+        Ok(Self { group, channels, ttl, interface })
+    }
+
+    /// Returns the group address and port of a channel
+    pub fn channel_addr(&self, channel: u16) -> SocketAddrV4 {
+        let ip = Ipv4Addr::from(u32::from(*self.group.ip()).wrapping_add(channel as u32));
+        SocketAddrV4::new(ip, self.group.port().wrapping_add(channel))
+    }
+
+    /// Returns the channel a ticker is published on
+    pub fn channel_for(&self, ticker: &str) -> u16 {
+        // FNV-1a: стабильный хеш, одинаковый во всех версиях сервера
+        let hash = ticker
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3));
+        (hash % self.channels as u64) as u16
+    }
+}
+
+/// Publishes every quote of the fan-out to its multicast channel
+///
+/// One subscriber and one socket serve any number of listeners. Each channel is
+/// a stream of its own with sequence numbers starting at 1 and its own batching.
+/// The publisher is never disconnected by the overflow policy: when it falls behind,
+/// its queue keeps the newest quote of every ticker.
+pub struct MulticastPublisher {
+    socket: UdpSocket,
+    config: MulticastConfig,
+    batching: BatchConfig,
+}
+
+impl MulticastPublisher {
+    /// Creates the sending socket on the configured interface
+    pub fn new(config: MulticastConfig, batching: BatchConfig) -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_multicast_if_v4(&config.interface)?;
+        socket.set_multicast_ttl_v4(config.ttl)?;
+        // Подписчики на той же машине тоже должны получать котировки
+        socket.set_multicast_loop_v4(true)?;
+        socket.bind(&SocketAddr::V4(SocketAddrV4::new(config.interface, 0)).into())?;
+
+        Ok(Self {
+            socket: socket.into(),
+            config,
+            batching,
+        })
+    }
+
+    /// Starts the publishing thread
+    pub fn start(self, fanout: Arc<FanOut>) -> Result<(), Box<dyn std::error::Error>> {
+        // Отключение издателя остановило бы мультикаст для всех слушателей сразу
+        let mut reader = fanout.subscribe_with_policy(OverflowPolicy::ConflatePerTicker);
+
+        println!(
+            "[{}] Publishing quotes to {} multicast channel(s) from {}",
+            timestamp(),
+            self.config.channels,
+            self.config.group
+        );
+        info!("Publishing quotes to {} multicast channel(s) from {}", self.config.channels, self.config.group);
+
+        thread::spawn(move || {
+            let mut queues: Vec<FrameQueue> = (0..self.config.channels).map(|_| FrameQueue::new(self.batching)).collect();
+            let send = |channel: u16, frame: io::Result<Option<Vec<u8>>>| match frame {
+                Ok(Some(frame)) => {
+                    if let Err(e) = self.socket.send_to(&frame, self.config.channel_addr(channel)) {
+                        error!("Failed to publish to multicast channel {}: {}", channel, e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to encode quotes: {}", e),
+            };
+
+            loop {
+                // Wait for the next quote no longer than the most urgent pending batch may wait
+                let now = Instant::now();
+                let timeout = queues
+                    .iter()
+                    .filter_map(|queue| queue.wait_time(now))
+                    .min()
+                    .unwrap_or(Duration::from_millis(IDLE_WAIT_MS));
+
+                match reader.recv_timeout(timeout) {
+                    Ok(quote) => {
+                        let channel = self.config.channel_for(&quote.ticker);
+                        debug!("Publishing quote on multicast channel {}: {:?}", channel, quote);
+                        let (_, full) = queues[channel as usize].push(quote, Instant::now());
+                        send(channel, full);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let now = Instant::now();
+                for (channel, queue) in queues.iter_mut().enumerate() {
+                    send(channel as u16, queue.poll(now));
+                }
+            }
+            info!("Multicast publisher stopped");
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use quote_generator_lib::core::StockQuote;
+
+/// Caps on how fast a session sends quotes, set with the STREAM options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RateLimit {
+    /// `MAXRATE=<n>/s`: most quotes sent per second
+    pub max_rate: Option<u32>,
+    /// `THROTTLE=<n>ms`: least time between two quotes of the same ticker
+    pub throttle: Option<Duration>,
+}
+
+impl RateLimit {
+    /// Returns true if quotes are sent as soon as they arrive
+    pub fn is_unlimited(&self) -> bool {
+        self.max_rate.is_none() && self.throttle.is_none()
+    }
+}
+
+/// Parses a rate such as `50/s` or `50`, in quotes per second
+pub fn parse_rate(s: &str) -> Result<u32, String> {
+    let s = s.trim().to_lowercase();
+    match s.strip_suffix("/s").unwrap_or(&s).parse::<u32>() {
+        Ok(rate) if rate > 0 => Ok(rate),
+        Ok(_) => Err(format!("rate must be positive, got '{}'", s)),
+        Err(_) => Err(format!("invalid rate '{}', expected e.g. 50/s", s)),
+    }
+}
+
+/// Parses an interval such as `250ms` or `2s`
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let s = s.trim().to_lowercase();
+    let parsed = match s.strip_suffix("ms") {
+        Some(ms) => ms.parse::<u64>().map(Duration::from_millis),
+        None => s.strip_suffix('s').unwrap_or("").parse::<u64>().map(Duration::from_secs),
+    };
+    match parsed {
+        Ok(interval) if !interval.is_zero() => Ok(interval),
+        Ok(_) => Err(format!("interval must be positive, got '{}'", s)),
+        Err(_) => Err(format!("invalid interval '{}', expected e.g. 250ms or 2s", s)),
+    }
+}
+
+/// Token bucket allowing `rate` quotes per second with bursts of up to one second worth of quotes
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled = now;
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until the next token is available
+    fn wait_time(&self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        let missing = 1.0 - (self.tokens + elapsed * self.rate);
+        if missing > 0.0 {
+            Duration::from_secs_f64(missing / self.rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Holds back the quotes of a session that exceed its rate limit
+///
+/// A held back quote is replaced by a newer quote of the same ticker, so once the limit allows,
+/// the client gets the latest price rather than a stale one. A ticker that was just sent queues
+/// behind the others, so a busy ticker cannot take all of the rate.
+pub struct Pacer {
+    bucket: Option<TokenBucket>,
+    throttle: Option<Duration>,
+    last_sent: HashMap<String, Instant>,
+    pending: Vec<StockQuote>,
+    replaced: u64,
+}
+
+impl Pacer {
+    /// Creates a new Pacer enforcing `limit` from `now` on
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            bucket: limit.max_rate.map(|rate| TokenBucket::new(rate, now)),
+            throttle: limit.throttle,
+            last_sent: HashMap::new(),
+            pending: Vec::new(),
+            replaced: 0,
+        }
+    }
+
+    /// Queues a quote, replacing the held back quote of the same ticker
+    pub fn offer(&mut self, quote: StockQuote) {
+        match self.pending.iter_mut().find(|q| q.ticker == quote.ticker) {
+            Some(pending) => {
+                *pending = quote;
+                self.replaced += 1;
+            }
+            None => self.pending.push(quote),
+        }
+    }
+
+    /// Takes the quotes the limit allows to send now, in the order they were queued
+    pub fn release(&mut self, now: Instant) -> Vec<StockQuote> {
+        let mut released = Vec::new();
+        let mut i = 0;
+        while i < self.pending.len() {
+            if self.throttle_wait(&self.pending[i].ticker, now) > Duration::ZERO {
+                i += 1;
+                continue;
+            }
+            if let Some(bucket) = &mut self.bucket
+                && !bucket.try_take(now)
+            {
+                break;
+            }
+            let quote = self.pending.remove(i);
+            if self.throttle.is_some() {
+                self.last_sent.insert(quote.ticker.clone(), now);
+            }
+            released.push(quote);
+        }
+        released
+    }
+
+    /// How long until a held back quote may be sent, None when nothing is held back
+    pub fn wait_time(&self, now: Instant) -> Option<Duration> {
+        let bucket_wait = self.bucket.as_ref().map(|b| b.wait_time(now)).unwrap_or(Duration::ZERO);
+        self.pending
+            .iter()
+            .map(|quote| self.throttle_wait(&quote.ticker, now).max(bucket_wait))
+            .min()
+    }
+
+    /// Returns the number of quotes replaced by a newer one since the last call
+    pub fn take_replaced(&mut self) -> u64 {
+        std::mem::take(&mut self.replaced)
+    }
+
+    fn throttle_wait(&self, ticker: &str, now: Instant) -> Duration {
+        match (self.throttle, self.last_sent.get(ticker)) {
+            (Some(throttle), Some(&sent)) => (sent + throttle).saturating_duration_since(now),
+            _ => Duration::ZERO,
+        }
+    }
+}
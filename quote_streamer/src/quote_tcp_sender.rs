@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+use quote_generator_lib::timestamp;
+
+use crate::fanout::FanOut;
+use crate::quote_udp_sender::{SessionState, StreamOptions, StreamSession, broadcast_quotes};
+
+/// How long the client has to connect to the stream listener
+const ACCEPT_TIMEOUT_SECS: u64 = 10;
+const ACCEPT_POLL_INTERVAL_MS: u64 = 50;
+/// A client that takes no data for this long is dropped, a frame left half written cannot be sent again
+const WRITE_TIMEOUT_SECS: u64 = 5;
+
+/// TCP sender for clients that cannot receive UDP, e.g. behind NAT
+///
+/// Listens on a port of its own for one connection from the client IP, then writes the
+/// wire frames of the stream one after another; the frame header tells where each one ends.
+/// The stream stops when the client disconnects.
+pub struct TcpQuoteSender {
+    listener: TcpListener,
+    client_ip: IpAddr,
+    options: StreamOptions,
+}
+
+impl TcpQuoteSender {
+    /// Creates a new TcpQuoteSender listening on a free port of `bind_ip` for a connection from `client_ip`
+    pub fn new(bind_ip: IpAddr, client_ip: IpAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind((bind_ip, 0))?;
+        Ok(Self {
+            listener,
+            client_ip,
+            options: StreamOptions::default(),
+        })
+    }
+
+    /// Sets how quotes are packed into frames and delivered
+    pub fn with_options(mut self, options: StreamOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Starts waiting for the client and streaming to it
+    ///
+    /// Returns the session, holding the address the client has to connect to
+    pub fn start(self, tickers: String, fanout: Arc<FanOut>) -> Result<StreamSession, Box<dyn std::error::Error>> {
+        let server_addr = self.listener.local_addr()?.to_string();
+        let state = Arc::new(SessionState::new(&tickers));
+
+        let state_clone = Arc::clone(&state);
+        let server_addr_clone = server_addr.clone();
+        let streamer = thread::spawn(move || {
+            let mut stream = match accept(&self.listener, self.client_ip, &state_clone.shutdown) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("No client connected to TCP stream {}: {}", server_addr_clone, e);
+                    state_clone.shutdown.store(true, Ordering::Relaxed);
+                    return;
+                }
+            };
+            let client = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            println!("[{}] TCP stream client connected from {}", timestamp(), client);
+            info!("TCP stream client connected from {}", client);
+            let _ = stream.set_nodelay(true);
+            // Клиент, который перестал читать, не должен задерживать остановку сессии
+            let _ = stream.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECS)));
+
+            // Подписчик создаётся только после подключения, до этого котировки некому отправлять
+            let (snapshot, mut reader) = fanout.subscribe_with_snapshot();
+
+            let result = broadcast_quotes(&mut reader, snapshot, &state_clone, self.options, |frame| {
+                // Тайм-аут мог оборвать кадр на середине: повтор испортил бы поток, поэтому клиент отключается
+                stream.write_all(frame).map_err(|e| match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("client took no data for {}s", WRITE_TIMEOUT_SECS),
+                    ),
+                    _ => e,
+                })
+            });
+            match result {
+                Ok(()) => info!("TCP stream to {} finished", client),
+                Err(e) => {
+                    // Клиент закрыл соединение — это обычное завершение потока
+                    println!("[{}] TCP stream to {} stopped: {}", timestamp(), client, e);
+                    info!("TCP stream to {} stopped: {}", client, e);
+                }
+            }
+            state_clone.shutdown.store(true, Ordering::Relaxed);
+        });
+
+        Ok(StreamSession::new(server_addr, state).with_threads(vec![streamer]))
+    }
+}
+
+/// Waits up to `ACCEPT_TIMEOUT_SECS` for the client to connect, or until the session is stopped
+///
+/// Connections from other addresses than `client_ip` are closed, the stream belongs to the control connection client
+fn accept(listener: &TcpListener, client_ip: IpAddr, shutdown: &AtomicBool) -> io::Result<TcpStream> {
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + Duration::from_secs(ACCEPT_TIMEOUT_SECS);
+
+    loop {
+        match listener.accept() {
+            Ok((stream, peer)) if peer.ip() == client_ip => {
+                stream.set_nonblocking(false)?;
+                return Ok(stream);
+            }
+            Ok((_, peer)) => {
+                warn!("Rejected TCP stream connection from {}, expecting {}", peer, client_ip);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if shutdown.load(Ordering::Relaxed) {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "session stopped"));
+                }
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "accept timed out"));
+                }
+                thread::sleep(Duration::from_millis(ACCEPT_POLL_INTERVAL_MS));
+            }
+            Err(e) => {
+                error!("Failed to accept TCP stream client: {}", e);
+                return Err(e);
+            }
+        }
+    }
+}
@@ -1,83 +1,610 @@
 
+use std::io;
 use std::net::UdpSocket;
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::thread;
+use std::sync::{Mutex, RwLock};
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use bus::Bus;
 use log::{info, error, warn, debug};
 
-use quote_generator_lib::core::StockQuote;
+use quote_generator_lib::core::{QuoteBatcher, SequencedQuote, StockQuote, WireMessage};
 use quote_generator_lib::timestamp;
 
+use crate::fanout::{FanOut, Subscriber};
+use crate::pacing::{Pacer, RateLimit};
+
 const PING_TIMEOUT_SECS: u64 = 5;
-const PING_CHECK_INTERVAL_SECS: u64 = 1;
+/// Also bounds how long the timeout checker takes to notice a stopped session
+const PING_CHECK_INTERVAL_MS: u64 = 100;
 const SOCKET_READ_TIMEOUT_MS: u64 = 100;
+/// Number of most recent quotes each session keeps for retransmission
+pub const RETRANSMIT_BUFFER_SIZE: usize = 1000;
+/// Datagram size that fits the usual MTU without IP fragmentation
+pub const DEFAULT_MAX_DATAGRAM_SIZE: u16 = 1200;
+pub const DEFAULT_BATCH_FLUSH_MS: u64 = 20;
+/// Most quotes taken from the subscriber queue at once when conflating
+const MAX_CONFLATED_BACKLOG: usize = 1000;
+/// How often a socket that would block is tried again
+const BACKED_UP_RETRY_MS: u64 = 5;
+
+/// How quotes are packed into datagrams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchConfig {
+    /// Largest datagram sent, in bytes
+    pub max_datagram_size: usize,
+    /// Longest time a quote waits for others to share its datagram, zero sends every quote at once
+    pub flush_interval: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE as usize,
+            flush_interval: Duration::from_millis(DEFAULT_BATCH_FLUSH_MS),
+        }
+    }
+}
+
+/// Numbers the quotes of one stream and packs them into frames,
+/// sent once full or when the oldest pending quote has waited `flush_interval`
+pub struct FrameQueue {
+    seq: u64,
+    batcher: QuoteBatcher,
+    flush_interval: Duration,
+    flush_at: Option<Instant>,
+}
+
+impl FrameQueue {
+    /// Creates a new FrameQueue, the first quote gets sequence number 1
+    pub fn new(batching: BatchConfig) -> Self {
+        Self {
+            seq: 0,
+            batcher: QuoteBatcher::new(batching.max_datagram_size),
+            flush_interval: batching.flush_interval,
+            flush_at: None,
+        }
+    }
+
+    /// Numbers the quote and queues it, returning a full frame to send right away if any
+    pub fn push(&mut self, quote: StockQuote, now: Instant) -> (SequencedQuote, io::Result<Option<Vec<u8>>>) {
+        // Every quote of the stream gets the next sequence number, even if sending fails
+        self.seq += 1;
+        let sequenced = SequencedQuote::new(self.seq, quote);
+
+        let full = self.batcher.push(sequenced.clone());
+        if matches!(full, Ok(Some(_))) {
+            self.flush_at = None;
+        }
+        if self.flush_at.is_none() && !self.batcher.is_empty() {
+            self.flush_at = Some(now + self.flush_interval);
+        }
+        (sequenced, full)
+    }
+
+    /// Returns the pending frame once its deadline has passed
+    pub fn poll(&mut self, now: Instant) -> io::Result<Option<Vec<u8>>> {
+        match self.flush_at {
+            Some(flush_at) if now >= flush_at => {
+                self.flush_at = None;
+                self.batcher.flush()
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// How long the pending frame may still wait, None when nothing is pending
+    pub fn wait_time(&self, now: Instant) -> Option<Duration> {
+        self.flush_at.map(|flush_at| flush_at.saturating_duration_since(now))
+    }
+}
+
+/// Most recently sent quotes of a session, kept so lost datagrams can be sent again
+pub struct RetransmitBuffer {
+    capacity: usize,
+    quotes: VecDeque<SequencedQuote>,
+}
+
+impl RetransmitBuffer {
+    /// Creates a new RetransmitBuffer keeping at most `capacity` quotes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            quotes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Stores a sent quote, evicting the oldest one when full
+    pub fn push(&mut self, quote: SequencedQuote) {
+        if self.quotes.len() == self.capacity {
+            self.quotes.pop_front();
+        }
+        self.quotes.push_back(quote);
+    }
+
+    /// Returns the still buffered quotes with sequence numbers in `from..=to`
+    pub fn range(&self, from: u64, to: u64) -> Vec<SequencedQuote> {
+        // Номера в буфере идут подряд, поэтому позицию можно вычислить
+        let Some(first) = self.quotes.front().map(|q| q.seq) else {
+            return Vec::new();
+        };
+        let start = from.saturating_sub(first) as usize;
+        self.quotes
+            .iter()
+            .skip(start)
+            .take_while(|q| q.seq <= to)
+            .filter(|q| q.seq >= from)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Splits a comma-separated ticker list, skipping empty entries
+pub fn parse_tickers(tickers: &str) -> Vec<String> {
+    tickers
+        .split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// How the quotes of a session are delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StreamOptions {
+    pub batching: BatchConfig,
+    /// Send only the newest quote of each ticker among the quotes that piled up while the socket was busy
+    pub conflate: bool,
+    /// Caps on the rate quotes are sent at, held back quotes are conflated per ticker
+    pub limit: RateLimit,
+}
+
+/// Latest quote of each ticker, in the order the tickers first appeared
+#[derive(Debug, Default)]
+pub struct ConflationBuffer {
+    quotes: Vec<StockQuote>,
+    index: HashMap<String, usize>,
+    conflated: u64,
+}
+
+impl ConflationBuffer {
+    /// Adds a quote, replacing the buffered quote of the same ticker
+    pub fn push(&mut self, quote: StockQuote) {
+        match self.index.get(&quote.ticker) {
+            Some(&i) => {
+                self.quotes[i] = quote;
+                self.conflated += 1;
+            }
+            None => {
+                self.index.insert(quote.ticker.clone(), self.quotes.len());
+                self.quotes.push(quote);
+            }
+        }
+    }
+
+    /// Number of quotes replaced by a newer one
+    pub fn conflated(&self) -> u64 {
+        self.conflated
+    }
+
+    /// Returns the buffered quotes
+    pub fn into_quotes(self) -> Vec<StockQuote> {
+        self.quotes
+    }
+}
+
+/// State of a session shared by its threads and the control connection
+pub struct SessionState {
+    /// Tickers sent, changed on the fly by SUBSCRIBE and UNSUBSCRIBE
+    pub tickers: RwLock<HashSet<String>>,
+    /// Last `RETRANSMIT_BUFFER_SIZE` quotes sent
+    pub history: Mutex<RetransmitBuffer>,
+    /// Set to stop the session threads
+    pub shutdown: AtomicBool,
+    /// Quotes never sent because a newer quote of the same ticker replaced them
+    pub conflated: AtomicU64,
+    /// Last quotes of the tickers added by SUBSCRIBE, sent before the next quotes of the subscriber queue
+    pub pending_snapshot: Mutex<Vec<StockQuote>>,
+}
+
+impl SessionState {
+    /// Creates the state of a session sending the comma-separated `tickers`
+    pub fn new(tickers: &str) -> Self {
+        Self {
+            tickers: RwLock::new(parse_tickers(tickers).into_iter().collect()),
+            history: Mutex::new(RetransmitBuffer::new(RETRANSMIT_BUFFER_SIZE)),
+            shutdown: AtomicBool::new(false),
+            conflated: AtomicU64::new(0),
+            pending_snapshot: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns true if quotes of the ticker are sent
+    pub fn is_subscribed(&self, ticker: &str) -> bool {
+        self.tickers.read().map(|t| t.contains(ticker)).unwrap_or(false)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+}
+
+/// Source of session IDs, unique for the lifetime of the server
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A running stream started by `QuoteSender::start_broadcasting` or `TcpQuoteSender::start`
+///
+/// Dropping the session stops it and waits for its threads to finish
+pub struct StreamSession {
+    /// Identifies the session in STOP and LIST commands
+    pub id: u64,
+    pub server_addr: String,
+    state: Arc<SessionState>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl StreamSession {
+    /// Creates a new StreamSession sending from `server_addr` until `state.shutdown` is set
+    pub fn new(server_addr: String, state: Arc<SessionState>) -> Self {
+        Self {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            server_addr,
+            state,
+            threads: Vec::new(),
+        }
+    }
+
+    /// Sets the threads serving the session, they must end once `shutdown` is set
+    pub fn with_threads(mut self, threads: Vec<JoinHandle<()>>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Asks the stream threads to stop, they finish within a poll interval
+    pub fn stop(&self) {
+        self.state.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns false once the session was stopped, timed out or lost its client
+    pub fn is_running(&self) -> bool {
+        !self.state.is_stopped()
+    }
+
+    /// Adds tickers to the running stream, returns the tickers streamed from now on
+    ///
+    /// The last quotes of the added tickers are taken from `fanout` and sent first, so the client knows their prices right away
+    pub fn subscribe(&self, tickers: &[String], fanout: &FanOut) -> Vec<String> {
+        if let Ok(mut filter) = self.state.tickers.write() {
+            // Котировки новее снимка ждут в очереди подписчика, более старые поток отбросит
+            let snapshot = fanout
+                .snapshot()
+                .into_iter()
+                .filter(|quote| tickers.contains(&quote.ticker) && !filter.contains(&quote.ticker));
+            if let Ok(mut pending) = self.state.pending_snapshot.lock() {
+                pending.extend(snapshot);
+            }
+            filter.extend(tickers.iter().cloned());
+        }
+        self.tickers()
+    }
+
+    /// Removes tickers from the running stream, returns the tickers streamed from now on
+    pub fn unsubscribe(&self, tickers: &[String]) -> Vec<String> {
+        if let Ok(mut filter) = self.state.tickers.write() {
+            for ticker in tickers {
+                filter.remove(ticker);
+            }
+        }
+        self.tickers()
+    }
+
+    /// Returns the streamed tickers in alphabetical order
+    pub fn tickers(&self) -> Vec<String> {
+        let mut tickers: Vec<String> = match self.state.tickers.read() {
+            Ok(filter) => filter.iter().cloned().collect(),
+            Err(_) => Vec::new(),
+        };
+        tickers.sort();
+        tickers
+    }
+
+    /// Returns the quotes `from..=to` that are still available for retransmission
+    pub fn resend(&self, from: u64, to: u64) -> Vec<SequencedQuote> {
+        match self.state.history.lock() {
+            Ok(history) => history.range(from, to),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns how many quotes were conflated away so far
+    pub fn conflated(&self) -> u64 {
+        self.state.conflated.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for StreamSession {
+    fn drop(&mut self) {
+        self.stop();
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                error!("A thread of session {} panicked", self.id);
+            }
+        }
+        debug!("Session {} sending from {} closed", self.id, self.server_addr);
+    }
+}
+
+/// Takes `first` and the quotes already waiting behind it, keeping the newest quote of each subscribed ticker
+fn take_conflated(reader: &mut Subscriber, first: StockQuote, state: &SessionState) -> Vec<StockQuote> {
+    let mut backlog = ConflationBuffer::default();
+    let waiting = std::iter::from_fn(|| reader.try_recv().ok()).take(MAX_CONFLATED_BACKLOG);
+    for quote in std::iter::once(first).chain(waiting) {
+        if state.is_subscribed(&quote.ticker) {
+            backlog.push(quote);
+        }
+    }
+    if backlog.conflated() > 0 {
+        debug!("Conflated {} quote(s) that piled up while the socket was busy", backlog.conflated());
+        state.conflated.fetch_add(backlog.conflated(), Ordering::Relaxed);
+    }
+    backlog.into_quotes()
+}
+
+/// Frames the socket did not take yet, sent in order once it drains
+struct Outbox<F> {
+    send: F,
+    unsent: VecDeque<Vec<u8>>,
+}
+
+impl<F: FnMut(&[u8]) -> io::Result<()>> Outbox<F> {
+    /// Sends an encoded frame, queued behind the frames still waiting for the socket
+    fn send(&mut self, frame: io::Result<Option<Vec<u8>>>) -> io::Result<()> {
+        match frame {
+            Ok(Some(frame)) => {
+                self.unsent.push_back(frame);
+                self.drain()
+            }
+            Ok(None) => Ok(()),
+            Err(e) => {
+                error!("Failed to encode quotes: {}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends the waiting frames until the socket would block
+    fn drain(&mut self) -> io::Result<()> {
+        while let Some(frame) = self.unsent.front() {
+            match (self.send)(frame) {
+                Ok(()) => {
+                    self.unsent.pop_front();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn is_backed_up(&self) -> bool {
+        !self.unsent.is_empty()
+    }
+}
+
+/// Sends the subscribed quotes of the fan-out as wire frames until the session stops, the subscription ends or `send` fails
+///
+/// Quotes are numbered from 1 in the order they are sent, kept in the session history for retransmission and packed
+/// into frames of at most `max_datagram_size` bytes, flushed after `flush_interval`. A `send` failing with
+/// `WouldBlock` means the socket is backed up and did not take the frame at all: the frame is tried again
+/// and no new quote is numbered until it drains. A `send` that may have written part of a frame must fail otherwise.
+/// With `conflate`, quotes that piled up meanwhile are collapsed to the newest one per ticker before they are
+/// numbered, so conflation never shows up as a gap; without it they wait in the subscriber queue.
+/// The same goes for the quotes held back by the rate `limit`.
+/// The `snapshot` quotes of the subscribed tickers are sent first, so the client knows the prices right away,
+/// and so are the snapshots of tickers added later by `StreamSession::subscribe`.
+pub fn broadcast_quotes(
+    reader: &mut Subscriber,
+    snapshot: Vec<StockQuote>,
+    state: &SessionState,
+    options: StreamOptions,
+    send: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut queue = FrameQueue::new(options.batching);
+    let mut outbox = Outbox {
+        send,
+        unsent: VecDeque::new(),
+    };
+
+    let mut pacer = (!options.limit.is_unlimited()).then(|| Pacer::new(options.limit, Instant::now()));
+
+    // Снимок уходит первым, следующие котировки подписчика уже новее
+    let mut received = snapshot;
+    // Котировки, пришедшие пока сокет был занят, по одной на тикер
+    let mut held = ConflationBuffer::default();
+    // Время снимка тикеров, добавленных на лету: котировки старше снимка ещё могут ждать в очереди
+    let mut snapshot_times: HashMap<String, u64> = HashMap::new();
+    while !state.is_stopped() {
+        outbox.drain()?;
+        if outbox.is_backed_up() {
+            let retry = Duration::from_millis(BACKED_UP_RETRY_MS);
+            if !options.conflate {
+                thread::sleep(retry);
+                continue;
+            }
+            match reader.recv_timeout(retry) {
+                Ok(quote) => {
+                    for quote in take_conflated(reader, quote, state) {
+                        held.push(quote);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) if reader.is_evicted() => {
+                    return Err(io::Error::other("client fell behind and was disconnected by the overflow policy"));
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            continue;
+        }
+
+        if let Ok(mut pending) = state.pending_snapshot.lock()
+            && !pending.is_empty()
+        {
+            for quote in pending.iter() {
+                snapshot_times.insert(quote.ticker.clone(), quote.timestamp);
+            }
+            received.splice(0..0, pending.drain(..));
+        }
+        if received.is_empty() {
+            let backlog = std::mem::take(&mut held);
+            if backlog.conflated() > 0 {
+                debug!("Conflated {} quote(s) that piled up while the socket was backed up", backlog.conflated());
+                state.conflated.fetch_add(backlog.conflated(), Ordering::Relaxed);
+            }
+            received = backlog.into_quotes();
+        }
+        if received.is_empty() {
+            // Wait for the next quote no longer than the pending batch or a held back quote may wait
+            let now = Instant::now();
+            let timeout = [queue.wait_time(now), pacer.as_ref().and_then(|p| p.wait_time(now))]
+                .into_iter()
+                .flatten()
+                .min()
+                .unwrap_or(Duration::from_millis(SOCKET_READ_TIMEOUT_MS));
+
+            // Receive quote from the subscriber queue
+            received = match reader.recv_timeout(timeout) {
+                Ok(quote) if options.conflate => take_conflated(reader, quote, state),
+                Ok(quote) => vec![quote],
+                Err(RecvTimeoutError::Timeout) => Vec::new(),
+                Err(RecvTimeoutError::Disconnected) if reader.is_evicted() => {
+                    return Err(io::Error::other("client fell behind and was disconnected by the overflow policy"));
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+        }
+
+        let quotes = match &mut pacer {
+            Some(pacer) => {
+                for quote in received.drain(..).filter(|q| state.is_subscribed(&q.ticker)) {
+                    pacer.offer(quote);
+                }
+                let released = pacer.release(Instant::now());
+                state.conflated.fetch_add(pacer.take_replaced(), Ordering::Relaxed);
+                released
+            }
+            None => std::mem::take(&mut received),
+        };
+
+        for quote in quotes {
+            // Only send quotes for subscribed tickers, the set may change between quotes
+            if !state.is_subscribed(&quote.ticker) {
+                continue;
+            }
+            match snapshot_times.get(&quote.ticker) {
+                Some(&time) if quote.timestamp < time => continue,
+                Some(&time) if quote.timestamp > time => {
+                    snapshot_times.remove(&quote.ticker);
+                }
+                _ => {}
+            }
+            println!("[{}] Broadcasting got: {:?}", timestamp(), quote);
+            debug!("Broadcasting quote: {:?}", quote);
+            let (sequenced, full) = queue.push(quote, Instant::now());
+            if let Ok(mut history) = state.history.lock() {
+                history.push(sequenced);
+            }
+            outbox.send(full)?;
+        }
+
+        outbox.send(queue.poll(Instant::now()))?;
+    }
+    if reader.overflows() > 0 {
+        warn!("Client fell behind, its queue overflowed {} time(s)", reader.overflows());
+    }
+    let conflated = state.conflated.load(Ordering::Relaxed);
+    if conflated > 0 {
+        info!("Conflated {} quote(s) while the client was behind", conflated);
+    }
+    Ok(())
+}
 
 /// UDP sender for broadcasting stock quotes to clients
 pub struct QuoteSender {
     socket: UdpSocket,
+    options: StreamOptions,
 }
 
 impl QuoteSender {
     /// Creates a new QuoteSender bound to the specified address
     pub fn new(bind_addr: &str) -> Result<Self, std::io::Error> {
         let socket = UdpSocket::bind(bind_addr)?;
-        Ok(Self { socket })
-    }    
+        Ok(Self {
+            socket,
+            options: StreamOptions::default(),
+        })
+    }
+
+    /// Sets how quotes are packed into datagrams and delivered
+    pub fn with_options(mut self, options: StreamOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-    /// Starts broadcasting quotes from the bus to the target address
+    /// Starts broadcasting quotes from the fan-out to the target address
     /// 
     /// Creates three threads:
-    /// - Ping listener: Receives ping messages from client and responds with pong
+    /// - Ping listener: Receives ping frames from client and responds with pong
     /// - Timeout checker: Monitors last ping time, shuts down after 5 seconds without ping
-    /// - Broadcasting: Sends filtered quotes to the connected client
+    /// - Broadcasting: Sends filtered quotes to the connected client, numbered from 1 in the order they are sent
+    ///   and packed into datagrams of at most `max_datagram_size` bytes, flushed after `flush_interval`,
+    ///   optionally conflated while the socket is busy
     /// 
-    /// Returns the server's local socket address for client connection
-    pub fn start_broadcasting_with_bus(
+    /// Returns the session, holding the server's local socket address for client connection
+    /// and the last `RETRANSMIT_BUFFER_SIZE` quotes sent; the threads end when it is dropped
+    pub fn start_broadcasting(
         self,
         target_addr: String,
         tickers: String,
-        bus: Arc<Mutex<Bus<StockQuote>>>,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let tickers = tickers
-            .split(",")
-            .map(|s| s.to_string())
-            .collect::<HashSet<String>>();
-
-        let mut bus = bus.lock().map_err(|_| "Bus lock poisoned")?;
-        let mut reader = bus.add_rx();
+        fanout: Arc<FanOut>,
+    ) -> Result<StreamSession, Box<dyn std::error::Error>> {
+        let (snapshot, mut reader) = fanout.subscribe_with_snapshot();
 
         // Connect socket to client address for bidirectional UDP communication
         self.socket.connect(&target_addr)?;
+        // A full send buffer must not block the broadcaster, it has to notice the backlog to conflate it
+        self.socket.set_nonblocking(true)?;
         let server_addr = self.socket.local_addr()?.to_string();
 
         // Shared state for coordinating thread shutdown
-        let shutdown = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(SessionState::new(&tickers));
         let last_ping = Arc::new(Mutex::new(Instant::now()));
 
         // Thread 1: Ping listener - receives ping messages from client and responds with pong
-        // This thread listens for incoming UDP packets and filters for "ping" messages
+        // This thread listens for incoming UDP packets and decodes them as wire frames, answering pings
         let socket_clone = self.socket.try_clone()?;
-        let shutdown_clone = Arc::clone(&shutdown);
+        let state_clone = Arc::clone(&state);
         let last_ping_clone = Arc::clone(&last_ping);
         let target_addr_clone = target_addr.clone();
         
-        thread::spawn(move || {
-            socket_clone.set_read_timeout(Some(Duration::from_millis(SOCKET_READ_TIMEOUT_MS))).ok();
+        let ping_listener = thread::spawn(move || {
             let mut buf = [0u8; 64];
             
             // Keep listening until shutdown flag is set
-            while !shutdown_clone.load(Ordering::Relaxed) {
-                if let Ok((size, src)) = socket_clone.recv_from(&mut buf) {
-                    // Check if received message is "ping"
-                    if let Ok(msg) = std::str::from_utf8(&buf[..size]) 
-                        && msg.trim() == "ping" {
-                            
+            while !state_clone.is_stopped() {
+                let (size, src) = match socket_clone.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(_) => {
+                        // Сокет неблокирующий ради отправки, поэтому ждём сами
+                        thread::sleep(Duration::from_millis(SOCKET_READ_TIMEOUT_MS));
+                        continue;
+                    }
+                };
+                match WireMessage::decode(&buf[..size]) {
+                    Ok(WireMessage::Ping) => {
                         println!("[{}] Received ping from {}", timestamp(), src);
                         debug!("Received ping from {}", src);
                         // Update last ping timestamp
@@ -85,22 +612,26 @@ impl QuoteSender {
                             *last_ping = Instant::now();
                         }
                         // Send pong response back to client
-                        let _ = socket_clone.send(b"pong");                        
+                        if let Ok(pong) = WireMessage::Pong.encode() {
+                            let _ = socket_clone.send(&pong);
+                        }
                     }
+                    Ok(other) => debug!("Ignoring unexpected message from {}: {:?}", src, other),
+                    Err(e) => warn!("Ignoring invalid datagram from {}: {}", src, e),
                 }
             }
             println!("[{}] Ping listener thread stopped for {}", timestamp(), target_addr_clone);
         });
 
         // Thread 2: Timeout checker - monitors last ping time and triggers shutdown if timeout
-        // Checks every second if client has sent a ping within the last 5 seconds
-        let shutdown_clone = Arc::clone(&shutdown);
+        // Checks every 100 ms if client has sent a ping within the last 5 seconds
+        let state_clone = Arc::clone(&state);
         let last_ping_clone = Arc::clone(&last_ping);
         let target_addr_clone2 = target_addr.clone();
         
-        thread::spawn(move || {
-            while !shutdown_clone.load(Ordering::Relaxed) {
-                thread::sleep(Duration::from_secs(PING_CHECK_INTERVAL_SECS));
+        let timeout_checker = thread::spawn(move || {
+            while !state_clone.is_stopped() {
+                thread::sleep(Duration::from_millis(PING_CHECK_INTERVAL_MS));
                 // Check if last ping was more than PING_TIMEOUT_SECS ago
                 if let Ok(last_ping) = last_ping_clone.lock() 
                     && last_ping.elapsed() > Duration::from_secs(PING_TIMEOUT_SECS) {
@@ -108,7 +639,7 @@ impl QuoteSender {
                     println!("[{}] [TIMEOUT] No ping from {} for {} seconds, shutting down all threads", timestamp(), target_addr_clone2, PING_TIMEOUT_SECS);
                     warn!("No ping from {} for {} seconds, shutting down all threads", target_addr_clone2, PING_TIMEOUT_SECS);
                     // Set shutdown flag to stop all threads
-                    shutdown_clone.store(true, Ordering::Relaxed);
+                    state_clone.shutdown.store(true, Ordering::Relaxed);
                     break;                    
                 }
             }
@@ -116,31 +647,34 @@ impl QuoteSender {
             info!("Timeout checker thread stopped for {}", target_addr_clone2);
         });
 
-        // Thread 3: Broadcasting - receives quotes from bus and sends to client
-        // Filters quotes by ticker and serializes them before sending via UDP
-        thread::spawn(move || {
-            while !shutdown.load(Ordering::Relaxed) {
-                // Receive quote from the pub-sub bus
-                if let Ok(quote) = reader.recv() {
-                    // Only send quotes for subscribed tickers
-                    if tickers.contains(&quote.ticker) {
-                        println!("[{}] Broadcasting with bus got: {:?}", timestamp(), quote);
-                        debug!("Broadcasting quote: {:?}", quote);
-                        // Serialize quote to binary format
-                        if let Ok(encoded) = bincode::serialize(&quote) {
-                            // Send serialized quote to connected client
-                            if let Err(e) = self.socket.send(&encoded) {
-                                eprintln!("[{}] Failed to send quote: {}", timestamp(), e);
-                                error!("Failed to send quote: {}", e);
-                            }
-                        }
+        // Thread 3: Broadcasting - receives quotes from the fan-out and sends to client
+        // Filters quotes by ticker, numbers and packs them into wire frames before sending via UDP
+        let state_clone = Arc::clone(&state);
+        let options = self.options;
+        let broadcaster = thread::spawn(move || {
+            let result = broadcast_quotes(&mut reader, snapshot, &state_clone, options, |frame| {
+                // Send serialized quotes to connected client, a lost datagram is not fatal
+                match self.socket.send(frame) {
+                    Ok(_) => Ok(()),
+                    // Буфер сокета полон: кадр отправится снова, когда он освободится
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(e),
+                    Err(e) => {
+                        eprintln!("[{}] Failed to send quote: {}", timestamp(), e);
+                        error!("Failed to send quote: {}", e);
+                        Ok(())
                     }
                 }
+            });
+            if let Err(e) = result {
+                error!("Broadcasting to {} failed: {}", target_addr, e);
             }
+            // Остальные потоки сессии тоже завершаются
+            state_clone.shutdown.store(true, Ordering::Relaxed);
             println!("[{}] Broadcasting thread stopped for {}", timestamp(), target_addr);
             info!("Broadcasting thread stopped for {}", target_addr);
         });
 
-        Ok(server_addr)
+        Ok(StreamSession::new(server_addr, state)
+            .with_threads(vec![ping_listener, timeout_checker, broadcaster]))
     }
 }
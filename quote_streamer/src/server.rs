@@ -1,21 +1,43 @@
-use bus::Bus;
-use log::{info, error};
+use log::{info, error, debug};
 use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
+use std::net::{IpAddr, TcpStream};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::thread;
 
 use chrono::Local;
 
-use quote_generator_lib::core::StockQuote;
 use quote_generator_lib::timestamp;
 
-use crate::quote_udp_sender::{QuoteSender};
+use crate::fanout::FanOut;
+use crate::multicast::MulticastConfig;
+use crate::pacing::{parse_interval, parse_rate};
+use crate::quote_tcp_sender::TcpQuoteSender;
+use crate::quote_udp_sender::{QuoteSender, StreamOptions, StreamSession, parse_tickers};
+use crate::tickers::Universe;
+
+/// Largest number of quotes a single RESEND command may ask for
+const MAX_RESEND_QUOTES: u64 = 1000;
+
+/// Settings shared by all client connections
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    /// Delivery options of every stream started with STREAM
+    pub stream: StreamOptions,
+    /// Multicast channels quotes are also published on, None when multicast is disabled
+    pub multicast: Option<MulticastConfig>,
+    /// Symbols and groups clients may subscribe to
+    pub universe: Arc<Universe>,
+}
 
 /// Initiates quote streaming to a client address
 /// 
-/// Returns the server's socket address on success, None on failure
-fn stream_quotes(addr: &str, tickers: &str, bus: Arc<Mutex<Bus<StockQuote>>>) -> Option<String> {
+/// Returns the started session on success, None on failure
+fn stream_quotes(
+    addr: &str,
+    tickers: &str,
+    fanout: Arc<FanOut>,
+    options: StreamOptions,
+) -> Option<StreamSession> {
     let addr = addr.to_string().clone();
     let tickers = tickers.to_string().clone();
 
@@ -28,8 +50,11 @@ fn stream_quotes(addr: &str, tickers: &str, bus: Arc<Mutex<Bus<StockQuote>>>) ->
 
     match QuoteSender::new("0.0.0.0:0") {
         Ok(quote_sender) => {
-            match quote_sender.start_broadcasting_with_bus(addr, tickers, bus) {
-                Ok(server_addr) => Some(server_addr),
+            match quote_sender.with_options(options).start_broadcasting(addr, tickers, Arc::clone(&fanout)) {
+                Ok(session) => {
+                    debug!("Fan-out now serves {} subscriber(s)", fanout.subscriber_count());
+                    Some(session)
+                }
                 Err(e) => {
                     eprintln!("[{}] Failed to start broadcasting: {}", timestamp(), e);
                     error!("Failed to start broadcasting: {}", e);
@@ -45,8 +70,178 @@ fn stream_quotes(addr: &str, tickers: &str, bus: Arc<Mutex<Bus<StockQuote>>>) ->
     }
 }
 
+/// Starts a TCP stream the client connects to on a port of its own, listening on `bind_ip` for `client_ip`
+///
+/// Returns the started session on success, None on failure
+fn stream_quotes_tcp(
+    bind_ip: IpAddr,
+    client_ip: IpAddr,
+    tickers: &str,
+    fanout: Arc<FanOut>,
+    options: StreamOptions,
+) -> Option<StreamSession> {
+    println!("[{}] Streaming quotes for tickers: {} over TCP", timestamp(), tickers);
+    info!("Streaming quotes for tickers: {} over TCP", tickers);
+
+    let result = TcpQuoteSender::new(bind_ip, client_ip)
+        .map_err(|e| e.into())
+        .and_then(|sender| sender.with_options(options).start(tickers.to_string(), Arc::clone(&fanout)));
+    match result {
+        Ok(session) => {
+            debug!("Fan-out now serves {} subscriber(s)", fanout.subscriber_count());
+            Some(session)
+        }
+        Err(e) => {
+            eprintln!("[{}] Failed to start TCP stream: {}", timestamp(), e);
+            error!("Failed to start TCP stream: {}", e);
+            None
+        }
+    }
+}
+
+/// Answers a RESEND command with the quotes still buffered by the session
+///
+/// The first line holds the number of quotes that follow, one `seq|quote` line each
+fn resend_response(session: &StreamSession, from: u64, to: u64) -> String {
+    let quotes = session.resend(from, to);
+    let mut response = format!("RESEND {} {} count: {}\n", from, to, quotes.len());
+    for quote in quotes {
+        response.push_str(&quote.to_string());
+        response.push('\n');
+    }
+    response
+}
+
+/// Answers a LIST command with the sessions started on the connection and not stopped with STOP
+///
+/// The first line holds the number of sessions that follow,
+/// one `<id> <state> server: <addr> tickers: <tickers> conflated: <n>` line each
+pub fn list_response(sessions: &[StreamSession]) -> String {
+    let mut response = format!("SESSIONS count: {}\n", sessions.len());
+    for session in sessions {
+        response.push_str(&format!(
+            "{} {} server: {} tickers: {} conflated: {}\n",
+            session.id,
+            if session.is_running() { "running" } else { "stopped" },
+            session.server_addr,
+            session.tickers().join(","),
+            session.conflated()
+        ));
+    }
+    response
+}
+
+/// Answers a SNAPSHOT command with the last quote of every requested ticker
+///
+/// The first line holds the number of quotes that follow, one `ticker|price|...` line each;
+/// tickers without a quote yet are left out
+pub fn snapshot_response(fanout: &FanOut, tickers: &[String]) -> String {
+    let quotes: Vec<_> = fanout
+        .snapshot()
+        .into_iter()
+        .filter(|quote| tickers.contains(&quote.ticker))
+        .collect();
+    let mut response = format!("SNAPSHOT count: {}\n", quotes.len());
+    for quote in quotes {
+        response.push_str(&quote.to_string());
+        response.push('\n');
+    }
+    response
+}
+
+/// Answers a SPECS command with the price rules of every requested ticker
+///
+/// The first line holds the number of tickers that follow, one `ticker|tick_size|precision` line each
+pub fn specs_response(universe: &Universe, tickers: &[String]) -> String {
+    let specs: Vec<_> = tickers
+        .iter()
+        .filter_map(|ticker| universe.spec(ticker).map(|spec| (ticker, spec)))
+        .collect();
+    let mut response = format!("SPECS count: {}\n", specs.len());
+    for (ticker, spec) in specs {
+        response.push_str(&format!("{}|{}|{}\n", ticker, spec.tick_size(), spec.precision()));
+    }
+    response
+}
+
+/// Answers a command naming a session that is not one of the connection
+fn no_session_response(id: u64) -> String {
+    format!("ERROR: no session {} on this connection\n", id)
+}
+
+/// Answers a command naming tickers the universe does not know, or patterns matching none of them
+pub fn unknown_tickers_response(unknown: &[String]) -> String {
+    format!("ERROR: unknown tickers: {}\n", unknown.join(","))
+}
+
+/// Parses the `<from> <to>` arguments of a RESEND command
+pub fn parse_resend_range<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<(u64, u64), &'static str> {
+    let from = args.next().and_then(|s| s.parse::<u64>().ok());
+    let to = args.next().and_then(|s| s.parse::<u64>().ok());
+    match (from, to) {
+        (Some(from), Some(to)) if from >= 1 && from <= to => {
+            if to - from >= MAX_RESEND_QUOTES {
+                Err("ERROR: RESEND is limited to 1000 quotes at once\n")
+            } else {
+                Ok((from, to))
+            }
+        }
+        _ => Err("ERROR: use like 'RESEND 1 10 20'\n"),
+    }
+}
+
+/// Parses the `KEY=value` options following the tickers of a STREAM command, applied on top of `defaults`
+///
+/// `MAXRATE=<n>/s` caps the quotes sent per second, `THROTTLE=<n>ms` sends at most one quote per ticker per interval
+pub fn parse_stream_options<'a>(
+    args: impl Iterator<Item = &'a str>,
+    defaults: StreamOptions,
+) -> Result<StreamOptions, String> {
+    let mut options = defaults;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) if key.eq_ignore_ascii_case("MAXRATE") => {
+                options.limit.max_rate = Some(parse_rate(value)?);
+            }
+            Some((key, value)) if key.eq_ignore_ascii_case("THROTTLE") => {
+                options.limit.throttle = Some(parse_interval(value)?);
+            }
+            _ => {
+                return Err(format!(
+                    "unknown STREAM option '{}', expected MAXRATE=<n>/s or THROTTLE=<n>ms",
+                    arg
+                ));
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Answers a MULTICAST command with the channel address of every ticker
+pub fn multicast_response(multicast: &MulticastConfig, tickers: &[String]) -> String {
+    let channels: Vec<String> = tickers
+        .iter()
+        .map(|t| format!("{}={}", t, multicast.channel_addr(multicast.channel_for(t))))
+        .collect();
+    format!("MULTICAST {}\n", channels.join(" "))
+}
+
+/// Stops the sessions of a control connection that went away and waits for their threads
+fn close_sessions(sessions: Vec<StreamSession>, reason: &str) {
+    if sessions.is_empty() {
+        return;
+    }
+    let ids: Vec<u64> = sessions.iter().map(|s| s.id).collect();
+    drop(sessions);
+    println!("[{}] Closed sessions {:?}: {}", timestamp(), ids, reason);
+    info!("Closed sessions {:?}: {}", ids, reason);
+}
+
 /// Handles a connected TCP client, processing commands and managing quote streams
-pub fn handle_client(stream: TcpStream, bus: Arc<Mutex<Bus<StockQuote>>>) {
+///
+/// The streams belong to the connection and are closed as soon as it ends.
+/// RESEND, SUBSCRIBE, UNSUBSCRIBE and STOP name the session of this connection they apply to
+pub fn handle_client(stream: TcpStream, fanout: Arc<FanOut>, config: ServerConfig) {
     let mut writer = stream.try_clone().expect("failed to clone stream");
     let mut reader = BufReader::new(stream);
 
@@ -54,12 +249,14 @@ pub fn handle_client(stream: TcpStream, bus: Arc<Mutex<Bus<StockQuote>>>) {
     let _ = writer.write_all(b"Welcome to the Quote Streamer!\n");
     let _ = writer.flush();
 
+    let mut sessions: Vec<StreamSession> = Vec::new();
     let mut line = String::new();
     loop {
         line.clear();
         match reader.read_line(&mut line) {
             Ok(0) => {
                 // EOF — клиент закрыл соединение
+                close_sessions(sessions, "control connection closed");
                 return;
             }
             Ok(_) => {
@@ -77,31 +274,126 @@ pub fn handle_client(stream: TcpStream, bus: Arc<Mutex<Bus<StockQuote>>>) {
                     
                     Some("STREAM") => {
                         let addr = parts.next();
-                        let tickers = parts.next();
-                        match (addr, tickers) {
-                            (Some(addr), Some(tickers)) if addr.starts_with("UDP://") => {
-                                let bus0 = Arc::clone(&bus);
-                                match stream_quotes(&addr[6..], tickers, bus0) {
-                                    Some(server_addr) => &format!(
-                                        "Got STREAM command addr: {} tickers: {} server: {}\n",
-                                        addr.to_lowercase(),
-                                        tickers,
-                                        server_addr
-                                    ),
+                        let tickers = parts.next().map(|t| config.universe.resolve(&parse_tickers(t)));
+                        match (addr, tickers, parse_stream_options(parts, config.stream)) {
+                            (Some(addr), Some(Ok(tickers)), Ok(options))
+                                if (addr.starts_with("UDP://") || addr == "TCP://") && !tickers.is_empty() =>
+                            {
+                                let tickers = &tickers.join(",");
+                                let fanout0 = Arc::clone(&fanout);
+                                let session = if addr == "TCP://" {
+                                    // Клиент подключается к тому же адресу, что и для управления, и только с того же адреса
+                                    match (writer.local_addr(), writer.peer_addr()) {
+                                        (Ok(local), Ok(peer)) => {
+                                            stream_quotes_tcp(local.ip(), peer.ip(), tickers, fanout0, options)
+                                        }
+                                        _ => None,
+                                    }
+                                } else {
+                                    stream_quotes(&addr[6..], tickers, fanout0, options)
+                                };
+                                match session {
+                                    Some(session) => {
+                                        let (id, server_addr) = (session.id, session.server_addr.clone());
+                                        sessions.push(session);
+                                        &format!(
+                                            "Got STREAM command addr: {} tickers: {} server: {} session: {}\n",
+                                            addr.to_lowercase(),
+                                            tickers,
+                                            server_addr,
+                                            id
+                                        )
+                                    }
                                     None => "ERROR: Failed to start streaming\n",
                                 }
                             }
-                            _ => "ERROR: use like 'STREAM udp://127.0.0.1:1234 AAPL,TSLA'\n",
+                            (Some(_), Some(Err(unknown)), _) => &unknown_tickers_response(&unknown),
+                            (Some(_), Some(_), Err(e)) => &format!("ERROR: {}\n", e),
+                            _ => "ERROR: use like 'STREAM udp://127.0.0.1:1234 AAPL,TSLA' or 'STREAM tcp:// AAPL,TSLA MAXRATE=50/s'\n",
+                        }
+                    }
+
+                    Some("MULTICAST") => {
+                        let tickers = parts.next().map(|t| config.universe.resolve(&parse_tickers(t)));
+                        match (config.multicast, tickers) {
+                            (Some(multicast), Some(Ok(tickers))) if !tickers.is_empty() => {
+                                &multicast_response(&multicast, &tickers)
+                            }
+                            (None, _) => "ERROR: multicast is not enabled on this server\n",
+                            (_, Some(Err(unknown))) => &unknown_tickers_response(&unknown),
+                            _ => "ERROR: use like 'MULTICAST AAPL,TSLA'\n",
+                        }
+                    }
+
+                    Some("RESEND") => match parts.next().and_then(|id| id.parse::<u64>().ok()) {
+                        Some(id) => match (sessions.iter().find(|s| s.id == id), parse_resend_range(parts)) {
+                            (Some(session), Ok((from, to))) => &resend_response(session, from, to),
+                            (None, _) => &no_session_response(id),
+                            (_, Err(e)) => e,
+                        },
+                        None => "ERROR: use like 'RESEND 1 10 20'\n",
+                    },
+                    Some(command @ ("SUBSCRIBE" | "UNSUBSCRIBE")) => {
+                        let id = parts.next().and_then(|id| id.parse::<u64>().ok());
+                        let tickers = parts.next().map(|t| config.universe.resolve(&parse_tickers(t)));
+                        match (id, tickers) {
+                            (Some(id), Some(Ok(tickers))) if !tickers.is_empty() => {
+                                match sessions.iter().find(|s| s.id == id) {
+                                    Some(session) => {
+                                        let streamed = if command == "SUBSCRIBE" {
+                                            session.subscribe(&tickers, &fanout)
+                                        } else {
+                                            session.unsubscribe(&tickers)
+                                        };
+                                        info!(
+                                            "{} {:?}, streaming {:?} to {}",
+                                            command, tickers, streamed, session.server_addr
+                                        );
+                                        &format!("TICKERS {}\n", streamed.join(","))
+                                    }
+                                    None => &no_session_response(id),
+                                }
+                            }
+                            (Some(_), Some(Err(unknown))) => &unknown_tickers_response(&unknown),
+                            _ => "ERROR: use like 'SUBSCRIBE 1 AAPL,TSLA' or 'UNSUBSCRIBE 1 TSLA'\n",
                         }
                     }
+                    Some("STOP") => match parts.next().and_then(|id| id.parse::<u64>().ok()) {
+                        Some(id) => match sessions.iter().position(|s| s.id == id) {
+                            Some(index) => {
+                                // Потоки сессии завершаются сами, ждать их здесь значит задерживать ответ
+                                let session = sessions.remove(index);
+                                session.stop();
+                                thread::spawn(move || drop(session));
+                                println!("[{}] Stopped session {} on request", timestamp(), id);
+                                info!("Stopped session {} on request", id);
+                                &format!("STOPPED {}\n", id)
+                            }
+                            None => &no_session_response(id),
+                        },
+                        None => "ERROR: use like 'STOP 1'\n",
+                    },
+
+                    Some("LIST") => &list_response(&sessions),
+                    Some("SNAPSHOT") => match parts.next().map(|t| config.universe.resolve(&parse_tickers(t))) {
+                        Some(Ok(tickers)) if !tickers.is_empty() => &snapshot_response(&fanout, &tickers),
+                        Some(Err(unknown)) => &unknown_tickers_response(&unknown),
+                        _ => "ERROR: use like 'SNAPSHOT AAPL,TSLA'\n",
+                    },
+                    Some("SPECS") => match parts.next().map(|t| config.universe.resolve(&parse_tickers(t))) {
+                        Some(Ok(tickers)) if !tickers.is_empty() => &specs_response(&config.universe, &tickers),
+                        Some(Err(unknown)) => &unknown_tickers_response(&unknown),
+                        _ => "ERROR: use like 'SPECS AAPL,TSLA'\n",
+                    },
                     _ => "Unknown command!\n",
                 };
 
                 let _ = writer.write_all(response.as_bytes());
                 let _ = writer.flush();
             }
-            Err(_) => {
+            Err(e) => {
                 // ошибка чтения — закрываем
+                close_sessions(sessions, &e.to_string());
                 return;
             }
         }
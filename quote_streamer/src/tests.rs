@@ -1,10 +1,29 @@
 #[cfg(test)]
-mod tests {
-    use quote_generator_lib::core::StockQuote;
+mod unit {
+    use clap::Parser;
+    use quote_generator_lib::core::{LiquidityTier, ReplaySpeed, SequencedQuote, StockQuote, WireMessage};
+
+    use crate::Cli;
+    use crate::fanout::{FanOut, OverflowPolicy};
+    use crate::multicast::{MulticastConfig, MulticastPublisher};
+    use crate::pacing::{Pacer, RateLimit, parse_interval, parse_rate};
+    use crate::quote_tcp_sender::TcpQuoteSender;
+    use crate::quote_udp_sender::BatchConfig;
+    use crate::quote_udp_sender::{
+        ConflationBuffer, RetransmitBuffer, SessionState, StreamOptions, StreamSession, broadcast_quotes, parse_tickers,
+    };
+    use crate::server::{
+        ServerConfig, handle_client, list_response, multicast_response, parse_resend_range, parse_stream_options,
+        snapshot_response, specs_response,
+    };
+    use crate::tickers;
+    use crate::ws_gateway::{WsGateway, apply_request};
 
     #[test]
     fn stock_quote_serialization() {
-        let quote = StockQuote::new("TSLA", 250.5, 5000, 1234567890);
+        let quote = StockQuote::new("TSLA", 250.5, 5000, 1234567890)
+            .with_book(250.4, 250.5, 300, 100)
+            .with_last_size(25);
         let serialized = bincode::serialize(&quote).unwrap();
         let deserialized: StockQuote = bincode::deserialize(&serialized).unwrap();
         
@@ -12,14 +31,833 @@ mod tests {
         assert_eq!(deserialized.price, 250.5);
         assert_eq!(deserialized.volume, 5000);
         assert_eq!(deserialized.timestamp, 1234567890);
+        assert_eq!(deserialized.bid, 250.4);
+        assert_eq!(deserialized.ask, 250.5);
+        assert_eq!(deserialized.bid_size, 300);
+        assert_eq!(deserialized.ask_size, 100);
+        assert_eq!(deserialized.last_size, 25);
+    }
+
+    #[test]
+    fn sequenced_quote_serialization() {
+        let sequenced = SequencedQuote::new(42, StockQuote::new("TSLA", 250.5, 5000, 1234567890));
+        let serialized = bincode::serialize(&sequenced).unwrap();
+        let deserialized: SequencedQuote = bincode::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized.seq, 42);
+        assert_eq!(deserialized, sequenced);
     }
 
     #[test]
     fn ping_pong_messages() {
-        let ping = b"ping";
-        let pong = b"pong";
-        
-        assert_eq!(std::str::from_utf8(ping).unwrap().trim(), "ping");
-        assert_eq!(std::str::from_utf8(pong).unwrap().trim(), "pong");
+        let ping = WireMessage::Ping.encode().unwrap();
+        let pong = WireMessage::Pong.encode().unwrap();
+
+        assert_eq!(WireMessage::decode(&ping).unwrap(), WireMessage::Ping);
+        assert_eq!(WireMessage::decode(&pong).unwrap(), WireMessage::Pong);
+        assert!(WireMessage::decode(b"ping").is_err());
+    }
+
+    #[test]
+    fn cli_seed_is_optional() {
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "127.0.0.1", "-p", "7777"]).unwrap();
+        assert_eq!(cli.seed, None);
+
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "127.0.0.1", "-p", "7777", "--seed", "42"]).unwrap();
+        assert_eq!(cli.seed, Some(42));
+    }
+
+    #[test]
+    fn built_in_universe_has_price_rules() {
+        let instruments = tickers::get_instruments();
+        assert_eq!(instruments.len(), tickers::get_tickers().len());
+        assert!(instruments.iter().all(|i| i.spec.tick_size().raw() > 0));
+    }
+
+    #[test]
+    fn tickers_from_csv() {
+        let csv = "symbol,start_price,volatility,tier,tick_size,precision\n\
+                   # comment\n\
+                   aapl,183.15,0.3,high,,\n\
+                   PENNY,0.5,,low,0.0001,4\n\
+                   XYZ,,,,,\n";
+        let instruments = tickers::parse_csv(csv).unwrap();
+        assert_eq!(instruments.len(), 3);
+
+        assert_eq!(instruments[0].symbol, "AAPL");
+        assert_eq!(instruments[0].start_price, Some("183.15".parse().unwrap()));
+        assert_eq!(instruments[0].volatility, Some(0.3));
+        assert_eq!(instruments[0].tier, LiquidityTier::High);
+
+        assert_eq!(instruments[1].spec.tick_size().to_string(), "0.0001");
+        assert_eq!(instruments[1].spec.precision(), 4);
+
+        assert_eq!(instruments[2].start_price, None);
+        assert_eq!(instruments[2].tier, LiquidityTier::Low);
+    }
+
+    #[test]
+    fn built_in_universe_has_groups() {
+        let instruments = tickers::get_instruments();
+        assert!(instruments.iter().all(|i| i.group.is_some()), "every built-in ticker belongs to a group");
+        assert_eq!(instruments.iter().find(|i| i.symbol == "NVDA").unwrap().group.as_deref(), Some("TECH"));
+
+        let csv = "symbol,group\nAAPL,\nXYZ,crypto\n";
+        let instruments = tickers::parse_csv(csv).unwrap();
+        assert_eq!(instruments[0].group.as_deref(), Some("TECH"));
+        assert_eq!(instruments[1].group.as_deref(), Some("CRYPTO"));
+        assert!(tickers::parse_csv("symbol,group\nXYZ,new coins\n").is_err());
+    }
+
+    #[test]
+    fn universe_resolves_patterns() {
+        let universe = tickers::Universe::new(&tickers::get_instruments());
+        let resolve = |patterns: &str| universe.resolve(&parse_tickers(patterns));
+
+        assert_eq!(resolve("MSFT,AAPL"), Ok(vec!["MSFT".to_string(), "AAPL".to_string()]));
+        assert_eq!(resolve("*").unwrap().len(), tickers::get_tickers().len());
+        assert_eq!(resolve("M*T"), Ok(vec!["MDT".to_string(), "MSFT".to_string()]));
+        assert_eq!(resolve("T*X"), Ok(vec!["TJX".to_string()]));
+        assert_eq!(resolve("*RT*"), Ok(vec!["RTX".to_string(), "VRTX".to_string()]));
+
+        let starting_with_a = resolve("A*").unwrap();
+        assert!(starting_with_a.iter().all(|t| t.starts_with('A')));
+        assert!(starting_with_a.contains(&"AMZN".to_string()));
+
+        // Группа и явный тикер не дают повторов
+        let tech = resolve("AAPL,@TECH").unwrap();
+        assert_eq!(tech[0], "AAPL");
+        assert_eq!(tech.iter().filter(|t| *t == "AAPL").count(), 1);
+        assert!(tech.contains(&"NVDA".to_string()));
+
+        assert_eq!(
+            resolve("AAPL,APPL,@NOPE,X*"),
+            Err(vec!["APPL".to_string(), "@NOPE".to_string(), "X*".to_string()])
+        );
+    }
+
+    #[test]
+    fn specs_response_lists_price_rules() {
+        let instruments = tickers::parse_csv("symbol,tick_size,precision\nAAPL,,\nPENNY,0.0001,4\n").unwrap();
+        let universe = tickers::Universe::new(&instruments);
+        assert_eq!(
+            specs_response(&universe, &parse_tickers("PENNY,AAPL")),
+            "SPECS count: 2\nPENNY|0.0001|4\nAAPL|0.01|2\n"
+        );
+    }
+
+    #[test]
+    fn tickers_from_csv_invalid() {
+        assert!(tickers::parse_csv("").is_err());
+        assert!(tickers::parse_csv("ticker,price\nAAPL,1\n").is_err());
+        assert!(tickers::parse_csv("symbol,tier\nAAPL,huge\n").is_err());
+        assert!(tickers::parse_csv("symbol,start_price\nAAPL,-1\n").is_err());
+        assert!(tickers::parse_csv("symbol\nAAPL\naapl\n").is_err());
+        assert!(tickers::parse_csv("symbol,tick_size\nAAPL,0.001\n").is_err());
+        assert!(tickers::parse_csv("symbol,start_price\nAAPL,183.125\n").is_err());
+        assert!(tickers::parse_csv("symbol,start_price,tick_size\nAAPL,183.25,0.05\n").is_ok());
+        assert!(tickers::parse_csv("symbol,start_price,tick_size\nAAPL,183.27,0.05\n").is_err());
+    }
+
+    #[test]
+    fn tickers_from_toml_and_json() {
+        let toml = r#"
+            [[instruments]]
+            symbol = "MSFT"
+            start_price = 410.5
+            volatility = 0.2
+            tier = "medium"
+
+            [[instruments]]
+            symbol = "T"
+        "#;
+        let instruments = tickers::parse_toml(toml).unwrap();
+        assert_eq!(instruments.len(), 2);
+        assert_eq!(instruments[0].start_price, Some("410.5".parse().unwrap()));
+        assert_eq!(instruments[0].tier, LiquidityTier::Medium);
+
+        let json = r#"{"instruments": [{"symbol": "MSFT", "start_price": 410.5, "tier": "high"}, {"symbol": "T"}]}"#;
+        let instruments = tickers::parse_json(json).unwrap();
+        assert_eq!(instruments.len(), 2);
+        assert_eq!(instruments[0].tier, LiquidityTier::High);
+
+        assert!(tickers::parse_json(r#"{"instruments": []}"#).is_err());
+        assert!(tickers::parse_json(r#"{"instruments": [{"symbol": "T", "colour": "red"}]}"#).is_err());
+    }
+
+    #[test]
+    fn tickers_file_extension() {
+        let path = std::env::temp_dir().join(format!("quote_streamer_tickers_{}.csv", std::process::id()));
+        std::fs::write(&path, "symbol\nAAPL\nMSFT\n").unwrap();
+        let instruments = tickers::load_instruments(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(instruments.unwrap().len(), 2);
+
+        let path = path.with_extension("xml");
+        std::fs::write(&path, "symbol\nAAPL\n").unwrap();
+        let instruments = tickers::load_instruments(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(instruments.is_err());
+    }
+
+    #[test]
+    fn cli_replay_options() {
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "127.0.0.1", "-p", "7777"]).unwrap();
+        assert!(cli.replay.is_empty());
+        assert_eq!(cli.replay_speed, ReplaySpeed::Multiplier(1.0));
+
+        let cli = Cli::try_parse_from([
+            "quote_streamer", "-H", "127.0.0.1", "-p", "7777", "--replay", "day.bin", "--replay", "day2.csv",
+            "--replay-speed", "max",
+        ])
+        .unwrap();
+        assert_eq!(cli.replay, ["day.bin", "day2.csv"].map(std::path::PathBuf::from));
+        assert_eq!(cli.replay_speed, ReplaySpeed::Max);
+
+        assert!(Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--replay", "a", "--replay-speed", "fast"]).is_err());
+        // Скорость без записи для воспроизведения не имеет смысла
+        assert!(Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--replay-speed", "max"]).is_err());
+    }
+
+    #[test]
+    fn cli_batching_options() {
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "127.0.0.1", "-p", "7777"]).unwrap();
+        assert_eq!(cli.max_datagram_size, 1200);
+        assert_eq!(cli.batch_flush_ms, 20);
+
+        let cli = Cli::try_parse_from([
+            "quote_streamer", "-H", "127.0.0.1", "-p", "7777", "--max-datagram-size", "8000", "--batch-flush-ms", "0",
+        ])
+        .unwrap();
+        assert_eq!(cli.max_datagram_size, 8000);
+        assert_eq!(cli.batch_flush_ms, 0);
+
+        assert!(Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--max-datagram-size", "10"]).is_err());
+        assert!(Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--max-datagram-size", "70000"]).is_err());
+    }
+
+    #[test]
+    fn retransmit_buffer_keeps_latest_quotes() {
+        let mut buffer = RetransmitBuffer::new(3);
+        for seq in 1..=5 {
+            buffer.push(SequencedQuote::new(seq, StockQuote::new("AAPL", 100.0 + seq as f64, 100, seq)));
+        }
+
+        let seqs = |quotes: Vec<SequencedQuote>| quotes.iter().map(|q| q.seq).collect::<Vec<_>>();
+        assert_eq!(seqs(buffer.range(4, 4)), vec![4]);
+        assert_eq!(seqs(buffer.range(1, 4)), vec![3, 4]);
+        assert_eq!(seqs(buffer.range(3, 10)), vec![3, 4, 5]);
+        assert!(buffer.range(6, 8).is_empty());
+        assert!(RetransmitBuffer::new(3).range(1, 2).is_empty());
+    }
+
+    fn session(server_addr: &str, tickers: &str) -> StreamSession {
+        StreamSession::new(server_addr.to_string(), std::sync::Arc::new(SessionState::new(tickers)))
+    }
+
+    #[test]
+    fn session_tickers_change() {
+        let fanout = FanOut::new(10, OverflowPolicy::DropOldest);
+        let session = session("127.0.0.1:5555", "AAPL,,MSFT");
+        assert_eq!(session.tickers(), ["AAPL", "MSFT"]);
+
+        assert_eq!(session.subscribe(&parse_tickers("TSLA,AAPL"), &fanout), ["AAPL", "MSFT", "TSLA"]);
+        assert_eq!(session.unsubscribe(&parse_tickers("MSFT,GOOGL")), ["AAPL", "TSLA"]);
+        assert!(session.unsubscribe(&parse_tickers("AAPL,TSLA")).is_empty());
+    }
+
+    #[test]
+    fn sessions_listed_and_stopped() {
+        let sessions = vec![session("127.0.0.1:5555", "AAPL,MSFT"), session("127.0.0.1:5556", "TSLA")];
+        let (first, second) = (sessions[0].id, sessions[1].id);
+        assert_ne!(first, second);
+
+        sessions[1].stop();
+        assert!(sessions[0].is_running());
+        assert!(!sessions[1].is_running());
+        assert_eq!(
+            list_response(&sessions),
+            format!(
+                "SESSIONS count: 2\n{} running server: 127.0.0.1:5555 tickers: AAPL,MSFT conflated: 0\n\
+                 {} stopped server: 127.0.0.1:5556 tickers: TSLA conflated: 0\n",
+                first, second
+            )
+        );
+        assert_eq!(list_response(&[]), "SESSIONS count: 0\n");
+    }
+
+    #[test]
+    fn resend_range_parsing() {
+        assert_eq!(parse_resend_range("10 20".split_whitespace()), Ok((10, 20)));
+        assert_eq!(parse_resend_range("7 7".split_whitespace()), Ok((7, 7)));
+        assert!(parse_resend_range("20 10".split_whitespace()).is_err());
+        assert!(parse_resend_range("0 10".split_whitespace()).is_err());
+        assert!(parse_resend_range("10".split_whitespace()).is_err());
+        assert!(parse_resend_range("1 5000".split_whitespace()).is_err());
+    }
+
+    fn immediate_batching() -> BatchConfig {
+        BatchConfig {
+            max_datagram_size: 1200,
+            flush_interval: std::time::Duration::ZERO,
+        }
+    }
+
+    fn multicast_config(port: u16, channels: u16) -> MulticastConfig {
+        MulticastConfig::new(
+            std::net::SocketAddrV4::new("239.255.77.1".parse().unwrap(), port),
+            channels,
+            0,
+            "127.0.0.1".parse().unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn multicast_channels() {
+        let config = multicast_config(7000, 4);
+        assert_eq!(config.channel_addr(0).to_string(), "239.255.77.1:7000");
+        assert_eq!(config.channel_addr(3).to_string(), "239.255.77.4:7003");
+
+        let channel = config.channel_for("AAPL");
+        assert!(channel < 4);
+        assert_eq!(config.channel_for("AAPL"), channel);
+        assert_eq!(
+            multicast_response(&config, &parse_tickers("AAPL")),
+            format!("MULTICAST AAPL={}\n", config.channel_addr(channel))
+        );
+
+        let group = std::net::SocketAddrV4::new("10.0.0.1".parse().unwrap(), 7000);
+        assert!(MulticastConfig::new(group, 4, 1, std::net::Ipv4Addr::UNSPECIFIED).is_err());
+        let group = std::net::SocketAddrV4::new("239.255.0.1".parse().unwrap(), 65534);
+        assert!(MulticastConfig::new(group, 4, 1, std::net::Ipv4Addr::UNSPECIFIED).is_err());
+        assert!(MulticastConfig::new(group, 0, 1, std::net::Ipv4Addr::UNSPECIFIED).is_err());
+    }
+
+    #[test]
+    fn multicast_publishes_on_loopback() {
+        // Порт выбирает система, единственный канал группы слушает на нём
+        let socket = std::net::UdpSocket::bind(("0.0.0.0", 0)).unwrap();
+        let config = multicast_config(socket.local_addr().unwrap().port(), 1);
+        let fanout = std::sync::Arc::new(FanOut::new(10, OverflowPolicy::DropOldest));
+
+        let addr = config.channel_addr(config.channel_for("AAPL"));
+        socket.join_multicast_v4(addr.ip(), &config.interface).unwrap();
+        socket.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+
+        MulticastPublisher::new(config, immediate_batching()).unwrap().start(std::sync::Arc::clone(&fanout)).unwrap();
+        let quote = StockQuote::new("AAPL", 183.15, 100, 1);
+        fanout.publish(quote.clone());
+
+        let mut buf = [0u8; 2048];
+        let size = socket.recv(&mut buf).unwrap();
+        assert_eq!(
+            WireMessage::decode(&buf[..size]).unwrap(),
+            WireMessage::Quote(SequencedQuote::new(1, quote))
+        );
+    }
+
+    #[test]
+    fn tcp_sender_streams_frames() {
+        let fanout = std::sync::Arc::new(FanOut::new(10, OverflowPolicy::DropOldest));
+        let session = TcpQuoteSender::new("127.0.0.1".parse().unwrap(), "127.0.0.1".parse().unwrap())
+            .unwrap()
+            .with_options(StreamOptions {
+                batching: immediate_batching(),
+                ..StreamOptions::default()
+            })
+            .start("AAPL".to_string(), std::sync::Arc::clone(&fanout))
+            .unwrap();
+
+        let mut stream = std::net::TcpStream::connect(&session.server_addr).unwrap();
+        // Котировка, опубликованная до подписки, приходит со снимком, после — из потока
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+
+        let quote = StockQuote::new("AAPL", 183.15, 100, 1);
+        fanout.publish(StockQuote::new("MSFT", 410.5, 100, 1));
+        fanout.publish(quote.clone());
+
+        assert_eq!(
+            WireMessage::read_from(&mut stream).unwrap(),
+            Some(WireMessage::Quote(SequencedQuote::new(1, quote)))
+        );
+        assert_eq!(session.resend(1, 1).len(), 1);
+
+        // Подписка меняется на лету: сначала снимок добавленного тикера, нумерация продолжается
+        session.unsubscribe(&["AAPL".to_string()]);
+        session.subscribe(&["MSFT".to_string()], &fanout);
+        let msft = StockQuote::new("MSFT", 410.75, 100, 2);
+        fanout.publish(StockQuote::new("AAPL", 183.2, 100, 2));
+        fanout.publish(msft.clone());
+
+        assert_eq!(
+            WireMessage::read_from(&mut stream).unwrap(),
+            Some(WireMessage::Quote(SequencedQuote::new(2, StockQuote::new("MSFT", 410.5, 100, 1))))
+        );
+        assert_eq!(
+            WireMessage::read_from(&mut stream).unwrap(),
+            Some(WireMessage::Quote(SequencedQuote::new(3, msft)))
+        );
+    }
+
+    #[test]
+    fn tcp_sender_rejects_other_clients() {
+        let fanout = std::sync::Arc::new(FanOut::new(10, OverflowPolicy::DropOldest));
+        fanout.publish(StockQuote::new("AAPL", 183.15, 100, 1));
+        let session = TcpQuoteSender::new("127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap())
+            .unwrap()
+            .start("AAPL".to_string(), std::sync::Arc::clone(&fanout))
+            .unwrap();
+
+        // Подключение с чужого адреса закрывается, не получив даже снимка
+        let mut stream = std::net::TcpStream::connect(&session.server_addr).unwrap();
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+        assert!(matches!(WireMessage::read_from(&mut stream), Ok(None) | Err(_)));
+    }
+
+    #[test]
+    fn ws_subscription_requests() {
+        let mut subscriptions = std::collections::BTreeSet::new();
+
+        let reply = apply_request(&mut subscriptions, r#"{"action": "subscribe", "tickers": ["aapl", "MSFT", "TSLA"]}"#);
+        assert_eq!(reply, r#"{"type":"subscriptions","tickers":["AAPL","MSFT","TSLA"]}"#);
+        let reply = apply_request(&mut subscriptions, r#"{"action": "unsubscribe", "tickers": ["MSFT", "GOOGL"]}"#);
+        assert_eq!(reply, r#"{"type":"subscriptions","tickers":["AAPL","TSLA"]}"#);
+
+        let reply = apply_request(&mut subscriptions, r#"{"action": "buy", "tickers": ["AAPL"]}"#);
+        assert!(reply.starts_with(r#"{"type":"error","message":"Invalid request"#));
+        let reply = apply_request(&mut subscriptions, "AAPL");
+        assert!(reply.starts_with(r#"{"type":"error""#));
+        assert_eq!(subscriptions.len(), 2);
+    }
+
+    #[test]
+    fn ws_gateway_pushes_subscribed_quotes() {
+        let fanout = std::sync::Arc::new(FanOut::new(10, OverflowPolicy::DropOldest));
+        let gateway = WsGateway::bind(([127, 0, 0, 1], 0).into()).unwrap();
+        let addr = gateway.local_addr().unwrap();
+        gateway.start(std::sync::Arc::clone(&fanout)).unwrap();
+
+        let (mut socket, _) = tungstenite::connect(format!("ws://{}", addr)).unwrap();
+        if let tungstenite::stream::MaybeTlsStream::Plain(stream) = socket.get_mut() {
+            stream.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+        }
+
+        // Ответ на подписку приходит, когда подписчик уже создан, следом — последняя котировка тикера
+        fanout.publish(StockQuote::new("AAPL", 183.1, 100, 1));
+        let request = r#"{"action": "subscribe", "tickers": ["AAPL"]}"#;
+        socket.send(tungstenite::Message::Text(request.to_string())).unwrap();
+        assert_eq!(next_json(&mut socket)["type"], "subscriptions");
+        assert_eq!(next_json(&mut socket)["price"], 183.1);
+
+        fanout.publish(StockQuote::new("MSFT", 410.5, 100, 1));
+        fanout.publish(StockQuote::new("AAPL", 183.15, 100, 2));
+
+        let quote = next_json(&mut socket);
+        assert_eq!(quote["type"], "quote");
+        assert_eq!(quote["ticker"], "AAPL");
+        assert_eq!(quote["price"], 183.15);
+    }
+
+    fn next_json<S: std::io::Read + std::io::Write>(socket: &mut tungstenite::WebSocket<S>) -> serde_json::Value {
+        match socket.read().unwrap() {
+            tungstenite::Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn control_connection_owns_its_streams() {
+        use std::io::{BufRead, Write};
+
+        let fanout = std::sync::Arc::new(FanOut::new(10, OverflowPolicy::DropOldest));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let fanout_clone = std::sync::Arc::clone(&fanout);
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let config = ServerConfig {
+                universe: std::sync::Arc::new(tickers::Universe::new(&tickers::get_instruments())),
+                ..ServerConfig::default()
+            };
+            handle_client(stream, fanout_clone, config);
+        });
+
+        let mut control = std::net::TcpStream::connect(addr).unwrap();
+        let mut reader = std::io::BufReader::new(control.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        // Опечатка в тикере не создаёт сессию, которая никогда ничего не получит
+        control.write_all(b"STREAM tcp:// AAPL,APPL\n").unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "ERROR: unknown tickers: APPL\n");
+
+        control.write_all(b"STREAM tcp:// AAPL\n").unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let server_addr = line.split("server: ").nth(1).unwrap().split_whitespace().next().unwrap();
+
+        let id: u64 = line.split("session: ").nth(1).unwrap().trim().parse().unwrap();
+
+        let mut stream = std::net::TcpStream::connect(server_addr).unwrap();
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+
+        // Команды потока называют сессию явно
+        control.write_all(format!("SUBSCRIBE {} MSFT\n", id + 1).as_bytes()).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, format!("ERROR: no session {} on this connection\n", id + 1));
+        control.write_all(format!("SUBSCRIBE {} MSFT\n", id).as_bytes()).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "TICKERS AAPL,MSFT\n");
+
+        // Закрытие управляющего соединения завершает поток котировок сразу, без таймаутов
+        let started = std::time::Instant::now();
+        drop(reader);
+        control.shutdown(std::net::Shutdown::Both).unwrap();
+        assert_eq!(WireMessage::read_from(&mut stream).unwrap(), None);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    fn quote(ticker: &str, timestamp: u64) -> StockQuote {
+        StockQuote::new(ticker, 100.0 + timestamp as f64, 100, timestamp)
+    }
+
+    fn drain(subscriber: &mut crate::fanout::Subscriber) -> Vec<(String, u64)> {
+        std::iter::from_fn(|| subscriber.try_recv().ok()).map(|q| (q.ticker, q.timestamp)).collect()
+    }
+
+    #[test]
+    fn fanout_drop_oldest() {
+        let fanout = FanOut::new(3, OverflowPolicy::DropOldest);
+        let mut fast = fanout.subscribe();
+        let mut slow = fanout.subscribe();
+
+        for timestamp in 1..=2 {
+            fanout.publish(quote("AAPL", timestamp));
+        }
+        assert_eq!(drain(&mut fast).len(), 2);
+        for timestamp in 3..=5 {
+            fanout.publish(quote("AAPL", timestamp));
+        }
+
+        // Отстающий подписчик теряет старые котировки, остальные получают всё
+        assert_eq!(drain(&mut fast), [3, 4, 5].map(|t| ("AAPL".to_string(), t)));
+        assert_eq!(drain(&mut slow), [3, 4, 5].map(|t| ("AAPL".to_string(), t)));
+        assert_eq!(slow.overflows(), 2);
+        assert_eq!(fast.overflows(), 0);
+        assert!(slow.recv_timeout(std::time::Duration::from_millis(10)).is_err());
+    }
+
+    #[test]
+    fn fanout_conflate_per_ticker() {
+        let fanout = FanOut::new(3, OverflowPolicy::ConflatePerTicker);
+        let mut subscriber = fanout.subscribe();
+
+        fanout.publish(quote("AAPL", 1));
+        fanout.publish(quote("MSFT", 2));
+        fanout.publish(quote("TSLA", 3));
+        fanout.publish(quote("MSFT", 4));
+        fanout.publish(quote("GOOGL", 5));
+
+        assert_eq!(
+            drain(&mut subscriber),
+            [("MSFT", 4), ("TSLA", 3), ("GOOGL", 5)].map(|(t, ts)| (t.to_string(), ts))
+        );
+        assert_eq!(subscriber.overflows(), 2);
+    }
+
+    #[test]
+    fn fanout_disconnect_never_blocks_producer() {
+        let fanout = FanOut::new(2, OverflowPolicy::Disconnect);
+        let mut slow = fanout.subscribe();
+        let mut fast = fanout.subscribe();
+        // Подписчик со своей политикой не отключается, а сливает котировки
+        let mut publisher = fanout.subscribe_with_policy(OverflowPolicy::ConflatePerTicker);
+
+        for timestamp in 1..=1000 {
+            fanout.publish(quote("AAPL", timestamp));
+            fast.try_recv().unwrap();
+        }
+
+        assert!(slow.is_evicted());
+        assert_eq!(slow.try_recv(), Err(std::sync::mpsc::TryRecvError::Disconnected));
+        assert!(!fast.is_evicted());
+        assert!(!publisher.is_evicted());
+        assert_eq!(drain(&mut publisher), [("AAPL".to_string(), 1), ("AAPL".to_string(), 1000)]);
+
+        // Ушедший подписчик больше не получает котировок
+        drop(fast);
+        fanout.publish(quote("AAPL", 1001));
+        let mut late = fanout.subscribe();
+        fanout.publish(quote("AAPL", 1002));
+        assert_eq!(drain(&mut late), [("AAPL".to_string(), 1002)]);
+    }
+
+    #[test]
+    fn fanout_forgets_gone_subscribers() {
+        let fanout = FanOut::new(1, OverflowPolicy::Disconnect);
+        let first = fanout.subscribe();
+        let mut second = fanout.subscribe();
+        assert_eq!(fanout.subscriber_count(), 2);
+
+        // Без публикаций очередь ушедшего подписчика всё равно удаляется
+        drop(first);
+        assert_eq!(fanout.subscriber_count(), 1);
+
+        fanout.publish(quote("AAPL", 1));
+        fanout.publish(quote("AAPL", 2));
+        assert!(second.is_evicted());
+        assert_eq!(fanout.subscriber_count(), 0);
+        assert_eq!(second.try_recv(), Err(std::sync::mpsc::TryRecvError::Disconnected));
+
+        // Подписчик, переживший FanOut, завершается спокойно
+        let late = fanout.subscribe();
+        drop(fanout);
+        drop(late);
+    }
+
+    #[test]
+    fn fanout_snapshot_on_subscribe() {
+        let fanout = FanOut::new(10, OverflowPolicy::DropOldest);
+        assert!(fanout.subscribe_with_snapshot().0.is_empty());
+
+        fanout.publish(quote("MSFT", 1));
+        fanout.publish(quote("AAPL", 2));
+        fanout.publish(quote("MSFT", 3));
+        let (snapshot, mut subscriber) = fanout.subscribe_with_snapshot();
+        assert_eq!(tickers_of(snapshot), [("AAPL".to_string(), 2), ("MSFT".to_string(), 3)]);
+
+        // Подписчик получает только то, чего нет в снимке
+        fanout.publish(quote("TSLA", 4));
+        assert_eq!(drain(&mut subscriber), [("TSLA".to_string(), 4)]);
+        assert_eq!(
+            snapshot_response(&fanout, &parse_tickers("TSLA,AAPL,GOOGL")),
+            format!("SNAPSHOT count: 2\n{}\n{}\n", quote("AAPL", 2), quote("TSLA", 4))
+        );
+        assert_eq!(snapshot_response(&fanout, &parse_tickers("GOOGL")), "SNAPSHOT count: 0\n");
+    }
+
+    #[test]
+    fn broadcast_starts_with_snapshot() {
+        let fanout = FanOut::new(10, OverflowPolicy::DropOldest);
+        fanout.publish(quote("AAPL", 1));
+        fanout.publish(quote("TSLA", 2));
+        let (snapshot, mut reader) = fanout.subscribe_with_snapshot();
+        fanout.publish(quote("AAPL", 3));
+
+        let state = SessionState::new("AAPL");
+        let options = StreamOptions {
+            batching: immediate_batching(),
+            ..StreamOptions::default()
+        };
+        let mut sent = Vec::new();
+        broadcast_quotes(&mut reader, snapshot, &state, options, |frame| {
+            sent.push(WireMessage::decode(frame).unwrap());
+            if sent.len() == 2 {
+                state.shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        // Снимок нумеруется вместе с потоком, котировки чужих тикеров в него не попадают
+        assert_eq!(
+            sent,
+            [
+                WireMessage::Quote(SequencedQuote::new(1, quote("AAPL", 1))),
+                WireMessage::Quote(SequencedQuote::new(2, quote("AAPL", 3))),
+            ]
+        );
+    }
+
+    #[test]
+    fn conflation_buffer_keeps_newest_per_ticker() {
+        let mut buffer = ConflationBuffer::default();
+        for (ticker, timestamp) in [("AAPL", 1), ("MSFT", 2), ("AAPL", 3), ("TSLA", 4), ("AAPL", 5), ("MSFT", 6)] {
+            buffer.push(quote(ticker, timestamp));
+        }
+
+        // Порядок — по первому появлению тикера, значения — самые свежие
+        assert_eq!(buffer.conflated(), 3);
+        let quotes: Vec<_> = buffer.into_quotes().into_iter().map(|q| (q.ticker, q.timestamp)).collect();
+        assert_eq!(quotes, [("AAPL", 5), ("MSFT", 6), ("TSLA", 4)].map(|(t, ts)| (t.to_string(), ts)));
+    }
+
+    #[test]
+    fn broadcast_conflates_backlog() {
+        let fanout = FanOut::new(10, OverflowPolicy::DropOldest);
+        let mut reader = fanout.subscribe();
+        let state = SessionState::new("AAPL,MSFT");
+        let options = StreamOptions {
+            batching: immediate_batching(),
+            conflate: true,
+            ..StreamOptions::default()
+        };
+
+        // Котировки накопились, пока сокет был занят
+        for (ticker, timestamp) in [("AAPL", 1), ("MSFT", 2), ("AAPL", 3), ("TSLA", 4), ("AAPL", 5)] {
+            fanout.publish(quote(ticker, timestamp));
+        }
+
+        let mut sent = Vec::new();
+        broadcast_quotes(&mut reader, Vec::new(), &state, options, |frame| {
+            sent.push(WireMessage::decode(frame).unwrap());
+            state.shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap();
+
+        // Номера идут подряд: пропущенные котировки не выглядят как потери
+        assert_eq!(
+            sent,
+            [WireMessage::Batch(vec![
+                SequencedQuote::new(1, quote("AAPL", 5)),
+                SequencedQuote::new(2, quote("MSFT", 2)),
+            ])]
+        );
+        assert_eq!(state.conflated.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn broadcast_conflates_while_socket_would_block() {
+        let fanout = FanOut::new(10, OverflowPolicy::DropOldest);
+        let mut reader = fanout.subscribe();
+        let state = SessionState::new("AAPL,MSFT");
+        let options = StreamOptions {
+            batching: immediate_batching(),
+            conflate: true,
+            ..StreamOptions::default()
+        };
+        fanout.publish(quote("AAPL", 1));
+
+        let mut attempts = 0;
+        let mut sent = Vec::new();
+        broadcast_quotes(&mut reader, Vec::new(), &state, options, |frame| {
+            attempts += 1;
+            // Сокет занят первые две попытки, тем временем приходят новые котировки
+            if attempts <= 2 {
+                if attempts == 1 {
+                    for (ticker, timestamp) in [("AAPL", 2), ("MSFT", 3), ("TSLA", 4), ("AAPL", 5)] {
+                        fanout.publish(quote(ticker, timestamp));
+                    }
+                }
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            sent.push(WireMessage::decode(frame).unwrap());
+            if sent.len() == 2 {
+                state.shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        // Первый кадр отправлен повторно, накопившиеся котировки слиты по тикеру
+        assert_eq!(
+            sent,
+            [
+                WireMessage::Quote(SequencedQuote::new(1, quote("AAPL", 1))),
+                WireMessage::Batch(vec![
+                    SequencedQuote::new(2, quote("AAPL", 5)),
+                    SequencedQuote::new(3, quote("MSFT", 3)),
+                ]),
+            ]
+        );
+        assert_eq!(state.conflated.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn stream_options_parsing() {
+        assert_eq!(parse_rate("50/S"), Ok(50));
+        assert_eq!(parse_rate("7"), Ok(7));
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert_eq!(parse_interval("250MS"), Ok(std::time::Duration::from_millis(250)));
+        assert_eq!(parse_interval("2s"), Ok(std::time::Duration::from_secs(2)));
+        assert!(parse_interval("250").is_err());
+        assert!(parse_interval("0ms").is_err());
+
+        let defaults = StreamOptions {
+            conflate: true,
+            ..StreamOptions::default()
+        };
+        let options = parse_stream_options("MAXRATE=50/S THROTTLE=250MS".split_whitespace(), defaults).unwrap();
+        assert_eq!(options.limit.max_rate, Some(50));
+        assert_eq!(options.limit.throttle, Some(std::time::Duration::from_millis(250)));
+        assert!(options.conflate);
+        assert_eq!(parse_stream_options(std::iter::empty(), defaults), Ok(defaults));
+        assert!(parse_stream_options(["MAXRATE=50/S", "FAST"].into_iter(), defaults).is_err());
+        assert!(parse_stream_options(["THROTTLE=soon"].into_iter(), defaults).is_err());
+    }
+
+    fn tickers_of(quotes: Vec<StockQuote>) -> Vec<(String, u64)> {
+        quotes.into_iter().map(|q| (q.ticker, q.timestamp)).collect()
+    }
+
+    #[test]
+    fn pacer_caps_rate() {
+        let start = std::time::Instant::now();
+        let limit = RateLimit {
+            max_rate: Some(2),
+            throttle: None,
+        };
+        let mut pacer = Pacer::new(limit, start);
+        pacer.offer(quote("AAPL", 1));
+        pacer.offer(quote("MSFT", 2));
+        pacer.offer(quote("TSLA", 3));
+
+        // Запаса хватает на две котировки, третья ждёт следующего токена
+        assert_eq!(tickers_of(pacer.release(start)), [("AAPL".to_string(), 1), ("MSFT".to_string(), 2)]);
+        assert_eq!(pacer.wait_time(start), Some(std::time::Duration::from_millis(500)));
+        assert!(pacer.release(start + std::time::Duration::from_millis(250)).is_empty());
+
+        pacer.offer(quote("TSLA", 4));
+        assert_eq!(pacer.take_replaced(), 1);
+        assert_eq!(
+            tickers_of(pacer.release(start + std::time::Duration::from_millis(500))),
+            [("TSLA".to_string(), 4)]
+        );
+        assert_eq!(pacer.wait_time(start), None);
+    }
+
+    #[test]
+    fn pacer_throttles_per_ticker() {
+        let start = std::time::Instant::now();
+        let limit = RateLimit {
+            max_rate: None,
+            throttle: Some(std::time::Duration::from_millis(250)),
+        };
+        let mut pacer = Pacer::new(limit, start);
+        pacer.offer(quote("AAPL", 1));
+        assert_eq!(tickers_of(pacer.release(start)), [("AAPL".to_string(), 1)]);
+
+        let later = start + std::time::Duration::from_millis(100);
+        pacer.offer(quote("AAPL", 2));
+        pacer.offer(quote("MSFT", 3));
+        pacer.offer(quote("AAPL", 4));
+        assert_eq!(tickers_of(pacer.release(later)), [("MSFT".to_string(), 3)]);
+        assert_eq!(pacer.take_replaced(), 1);
+        assert_eq!(pacer.wait_time(later), Some(std::time::Duration::from_millis(150)));
+        assert_eq!(
+            tickers_of(pacer.release(start + std::time::Duration::from_millis(250))),
+            [("AAPL".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn cli_overflow_policy() {
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "127.0.0.1", "-p", "7777"]).unwrap();
+        assert_eq!(cli.overflow_policy, OverflowPolicy::DropOldest);
+
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--overflow-policy", "conflate-per-ticker"]).unwrap();
+        assert_eq!(cli.overflow_policy, OverflowPolicy::ConflatePerTicker);
+        assert!(Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--overflow-policy", "block"]).is_err());
+        assert!(!cli.conflate);
+
+        let cli = Cli::try_parse_from(["quote_streamer", "-H", "h", "-p", "1", "--conflate"]).unwrap();
+        assert!(cli.conflate);
     }
 }
@@ -1,3 +1,346 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use quote_generator_lib::core::{InstrumentSpec, LiquidityTier, Price};
+
+/// Instrument of the ticker universe together with its price rules and simulation profile
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    pub symbol: String,
+    pub spec: InstrumentSpec,
+    /// Mid price of the first generated quote, random when not set
+    pub start_price: Option<Price>,
+    /// Annualized volatility of the price model, model default when not set
+    pub volatility: Option<f64>,
+    pub tier: LiquidityTier,
+    /// Group clients subscribe to as a whole with `@<group>`, e.g. `TECH`
+    pub group: Option<String>,
+}
+
+impl Instrument {
+    /// Creates an instrument with the default price rules, profile and group for the symbol
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            spec: InstrumentSpec::US_EQUITY,
+            start_price: None,
+            volatility: None,
+            tier: LiquidityTier::for_ticker(symbol),
+            group: builtin_group(symbol).map(|g| g.to_string()),
+        }
+    }
+}
+
+/// One instrument as written in a universe file, every field but the symbol is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstrumentRecord {
+    symbol: String,
+    start_price: Option<Price>,
+    volatility: Option<f64>,
+    tier: Option<LiquidityTier>,
+    tick_size: Option<Price>,
+    precision: Option<u8>,
+    group: Option<String>,
+}
+
+impl InstrumentRecord {
+    fn into_instrument(self) -> Result<Instrument, String> {
+        let symbol = self.symbol.trim().to_uppercase();
+        if symbol.is_empty() || symbol.contains([',', '|']) || symbol.contains(char::is_whitespace) {
+            return Err(format!("invalid symbol '{}'", self.symbol));
+        }
+
+        let mut instrument = Instrument::new(&symbol);
+        if self.tick_size.is_some() || self.precision.is_some() {
+            let default = InstrumentSpec::US_EQUITY;
+            instrument.spec = InstrumentSpec::new(
+                self.tick_size.unwrap_or(default.tick_size()),
+                self.precision.unwrap_or(default.precision()),
+            )
+            .map_err(|e| format!("{}: {}", symbol, e))?;
+        }
+        if let Some(price) = self.start_price {
+            if price <= Price::ZERO {
+                return Err(format!("{}: start price must be positive, got {}", symbol, price));
+            }
+            // Цена вне сетки тиков так и осталась бы вне её до конца работы
+            let tick = instrument.spec.tick_size();
+            if price.round_to_tick(tick) != price {
+                return Err(format!("{}: start price {} is not a multiple of the tick size {}", symbol, price, tick));
+            }
+            instrument.start_price = Some(price);
+        }
+        if let Some(volatility) = self.volatility {
+            if !volatility.is_finite() || volatility < 0.0 {
+                return Err(format!("{}: volatility must be non-negative, got {}", symbol, volatility));
+            }
+            instrument.volatility = Some(volatility);
+        }
+        if let Some(tier) = self.tier {
+            instrument.tier = tier;
+        }
+        if let Some(group) = self.group {
+            let group = group.trim().to_uppercase();
+            if group.is_empty() || group.contains([',', '|', '*', '@']) || group.contains(char::is_whitespace) {
+                return Err(format!("{}: invalid group '{}'", symbol, group));
+            }
+            instrument.group = Some(group);
+        }
+        Ok(instrument)
+    }
+}
+
+/// Layout of TOML and JSON universe files
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UniverseFile {
+    instruments: Vec<InstrumentRecord>,
+}
+
+/// Returns the built-in ticker universe, all of them US equities quoted in cents
+pub fn get_instruments() -> Vec<Instrument> {
+    get_tickers()
+        .iter()
+        .map(|symbol| Instrument::new(symbol))
+        .collect()
+}
+
+/// Loads the ticker universe from a `.csv`, `.toml` or `.json` file
+///
+/// CSV files start with a header naming the columns: `symbol` is required,
+/// `start_price`, `volatility`, `tier` (high, medium, low), `tick_size`, `precision` and `group` are optional
+/// and may be left empty. TOML and JSON files hold the same fields in an `instruments` array.
+pub fn load_instruments(path: &Path) -> Result<Vec<Instrument>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("csv") => parse_csv(&content),
+        Some("toml") => parse_toml(&content),
+        Some("json") => parse_json(&content),
+        _ => Err(format!("unsupported tickers file '{}', expected .csv, .toml or .json", path.display()).into()),
+    }
+}
+
+/// Parses a CSV ticker universe
+pub fn parse_csv(content: &str) -> Result<Vec<Instrument>, Box<dyn Error>> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+    let (_, header) = lines.next().ok_or("tickers file is empty")?;
+    let columns: Vec<String> = header.split(',').map(|c| c.trim().to_lowercase()).collect();
+    if !columns.iter().any(|c| c == "symbol") {
+        return Err("CSV header has no 'symbol' column".into());
+    }
+
+    let mut records = Vec::new();
+    for (index, line) in lines {
+        let line_no = index + 1;
+        let cells: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        if cells.len() != columns.len() {
+            return Err(format!("line {}: expected {} columns, got {}", line_no, columns.len(), cells.len()).into());
+        }
+
+        let mut record = InstrumentRecord::default();
+        for (column, cell) in columns.iter().zip(cells).filter(|(_, cell)| !cell.is_empty()) {
+            let invalid = |e: String| format!("line {}: invalid {} '{}': {}", line_no, column, cell, e);
+            match column.as_str() {
+                "symbol" => record.symbol = cell.to_string(),
+                "start_price" => record.start_price = Some(cell.parse().map_err(invalid)?),
+                "volatility" => {
+                    record.volatility = Some(cell.parse().map_err(|e: std::num::ParseFloatError| invalid(e.to_string()))?)
+                }
+                "tier" => record.tier = Some(cell.parse().map_err(invalid)?),
+                "group" => record.group = Some(cell.to_string()),
+                "tick_size" => record.tick_size = Some(cell.parse().map_err(invalid)?),
+                "precision" => {
+                    record.precision = Some(cell.parse().map_err(|e: std::num::ParseIntError| invalid(e.to_string()))?)
+                }
+                other => return Err(format!("unknown CSV column '{}'", other).into()),
+            }
+        }
+        records.push(record);
+    }
+
+    into_universe(records)
+}
+
+/// Parses a TOML ticker universe
+pub fn parse_toml(content: &str) -> Result<Vec<Instrument>, Box<dyn Error>> {
+    let file: UniverseFile = toml::from_str(content)?;
+    into_universe(file.instruments)
+}
+
+/// Parses a JSON ticker universe
+pub fn parse_json(content: &str) -> Result<Vec<Instrument>, Box<dyn Error>> {
+    let file: UniverseFile = serde_json::from_str(content)?;
+    into_universe(file.instruments)
+}
+
+/// Validates the records and rejects empty universes and duplicate symbols
+fn into_universe(records: Vec<InstrumentRecord>) -> Result<Vec<Instrument>, Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    let mut instruments = Vec::with_capacity(records.len());
+
+    for record in records {
+        let instrument = record.into_instrument()?;
+        if !seen.insert(instrument.symbol.clone()) {
+            return Err(format!("duplicate symbol '{}'", instrument.symbol).into());
+        }
+        instruments.push(instrument);
+    }
+
+    if instruments.is_empty() {
+        return Err("tickers file defines no instruments".into());
+    }
+    Ok(instruments)
+}
+
+/// Symbols and groups of the universe, resolving the ticker patterns clients subscribe with
+#[derive(Debug, Default)]
+pub struct Universe {
+    symbols: Vec<String>,
+    groups: BTreeMap<String, Vec<String>>,
+    specs: BTreeMap<String, InstrumentSpec>,
+}
+
+impl Universe {
+    /// Creates the universe of the instruments
+    pub fn new(instruments: &[Instrument]) -> Self {
+        let mut symbols: Vec<String> = instruments.iter().map(|i| i.symbol.clone()).collect();
+        symbols.sort();
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for instrument in instruments {
+            if let Some(group) = &instrument.group {
+                groups.entry(group.clone()).or_default().push(instrument.symbol.clone());
+            }
+        }
+        for members in groups.values_mut() {
+            members.sort();
+        }
+        let specs = instruments.iter().map(|i| (i.symbol.clone(), i.spec)).collect();
+        Self { symbols, groups, specs }
+    }
+
+    /// Returns the price rules of a symbol of the universe
+    pub fn spec(&self, symbol: &str) -> Option<InstrumentSpec> {
+        self.specs.get(symbol).copied()
+    }
+
+    /// Expands ticker patterns into the symbols of the universe, without duplicates and in the order requested
+    ///
+    /// A pattern is a symbol, a wildcard such as `*` or `A*` where `*` stands for any characters, or a group
+    /// such as `@TECH`. Returns the patterns that match nothing, a typo like `APPL` included, as the error.
+    pub fn resolve(&self, patterns: &[String]) -> Result<Vec<String>, Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut tickers = Vec::new();
+        let mut unknown = Vec::new();
+
+        for pattern in patterns {
+            let matched: Vec<&String> = if let Some(group) = pattern.strip_prefix('@') {
+                self.groups.get(group).map(|members| members.iter().collect()).unwrap_or_default()
+            } else if pattern.contains('*') {
+                self.symbols.iter().filter(|symbol| wildcard_match(pattern, symbol)).collect()
+            } else {
+                self.symbols.iter().filter(|symbol| *symbol == pattern).collect()
+            };
+
+            if matched.is_empty() {
+                unknown.push(pattern.clone());
+            }
+            for symbol in matched {
+                if seen.insert(symbol) {
+                    tickers.push(symbol.clone());
+                }
+            }
+        }
+
+        if unknown.is_empty() { Ok(tickers) } else { Err(unknown) }
+    }
+}
+
+/// Matches a symbol against a pattern where `*` stands for any, possibly empty, run of characters
+fn wildcard_match(pattern: &str, symbol: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = symbol.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    // Последний кусок должен стоять в конце символа
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Sector groups of the built-in universe
+const BUILTIN_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "TECH",
+        &[
+            "AAPL", "ACN", "ADBE", "ADI", "ADP", "CRM", "CSCO", "FIS", "FISV", "GOOGL", "INTC", "INTU", "KLAC", "META",
+            "MSFT", "NFLX", "NVDA", "ORCL", "PYPL", "QCOM", "TXN",
+        ],
+    ),
+    ("COMM", &["CMCSA", "DIS", "T"]),
+    (
+        "FINANCE",
+        &[
+            "AON", "AXP", "BLK", "C", "COF", "GS", "ICE", "JPM", "MCO", "MS", "PGR", "PNC", "SCHW", "SPGI", "USB", "V",
+        ],
+    ),
+    (
+        "HEALTH",
+        &[
+            "ABBV", "ABT", "AMGN", "BDX", "BMY", "BSX", "CI", "DHR", "EW", "GILD", "HUM", "ISRG", "JNJ", "LLY", "MDT",
+            "PFE", "SYK", "TMO", "UNH", "VRTX", "ZTS",
+        ],
+    ),
+    (
+        "CONSUMER",
+        &[
+            "AMZN", "APTV", "BKNG", "CL", "COST", "HD", "LOW", "MCD", "MDLZ", "MO", "NKE", "PEP", "PG", "SBUX", "TGT",
+            "TJX", "TSLA",
+        ],
+    ),
+    (
+        "INDUSTRIAL",
+        &[
+            "CAT", "DE", "EMR", "ETN", "FDX", "GE", "HON", "ITW", "LMT", "MMM", "NOC", "NSC", "ROP", "RTX", "UNP", "UPS",
+            "WM",
+        ],
+    ),
+    ("ENERGY", &["SLB"]),
+    ("UTILITIES", &["AEP", "D", "DUK", "NEE", "SO"]),
+    ("MATERIALS", &["DD", "ECL", "LIN", "SHW"]),
+    ("REALESTATE", &["AMT", "PLD", "PSA"]),
+];
+
+/// Returns the sector group of a built-in ticker
+fn builtin_group(symbol: &str) -> Option<&'static str> {
+    BUILTIN_GROUPS
+        .iter()
+        .find(|(_, members)| members.contains(&symbol))
+        .map(|(group, _)| *group)
+}
+
 pub fn get_tickers() -> Vec<String> {
     vec![
         "AAPL".to_string(),
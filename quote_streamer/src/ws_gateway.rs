@@ -0,0 +1,235 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+use quote_generator_lib::core::StockQuote;
+use quote_generator_lib::timestamp;
+
+use crate::fanout::{FanOut, Subscriber};
+
+/// Longest wait for a quote before the socket is checked for client requests
+const REQUEST_POLL_INTERVAL_MS: u64 = 20;
+const HANDSHAKE_TIMEOUT_SECS: u64 = 5;
+/// Quotes waiting for a slow browser beyond this many bytes disconnect it
+const MAX_WRITE_BUFFER_SIZE: usize = 1 << 20;
+
+/// Request sent by a WebSocket client
+///
+/// `{"action": "subscribe", "tickers": ["AAPL", "MSFT"]}` or `{"action": "unsubscribe", "tickers": ["AAPL"]}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum WsRequest {
+    Subscribe { tickers: Vec<String> },
+    Unsubscribe { tickers: Vec<String> },
+}
+
+/// Message pushed to a WebSocket client, tagged with its `type`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum WsEvent<'a> {
+    /// A quote of a subscribed ticker, its fields follow the tag
+    Quote(&'a StockQuote),
+    /// Current subscriptions, sent after every request
+    Subscriptions { tickers: Vec<&'a str> },
+    Error { message: String },
+}
+
+impl WsEvent<'_> {
+    /// Serializes the event to a text frame
+    pub fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+/// Applies a subscription request and returns the reply to send back
+pub fn apply_request(subscriptions: &mut BTreeSet<String>, text: &str) -> String {
+    let event = match serde_json::from_str::<WsRequest>(text) {
+        Ok(WsRequest::Subscribe { tickers }) => {
+            subscriptions.extend(tickers.iter().map(|t| t.trim().to_uppercase()).filter(|t| !t.is_empty()));
+            None
+        }
+        Ok(WsRequest::Unsubscribe { tickers }) => {
+            for ticker in tickers {
+                subscriptions.remove(&ticker.trim().to_uppercase());
+            }
+            None
+        }
+        Err(e) => Some(WsEvent::Error {
+            message: format!("Invalid request: {}", e),
+        }),
+    };
+
+    let event = event.unwrap_or_else(|| WsEvent::Subscriptions {
+        tickers: subscriptions.iter().map(String::as_str).collect(),
+    });
+    serde_json::to_string(&event).unwrap_or_default()
+}
+
+/// WebSocket endpoint for browsers
+///
+/// Every connection gets a fan-out subscription of its own and starts with no subscriptions;
+/// quotes of the subscribed tickers are pushed as JSON text frames.
+pub struct WsGateway {
+    listener: TcpListener,
+}
+
+impl WsGateway {
+    /// Creates a new WsGateway listening on the given address
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    /// Returns the address the gateway listens on, with the port picked by the system when bound to port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Starts accepting WebSocket connections
+    pub fn start(self, fanout: Arc<FanOut>) -> io::Result<()> {
+        let addr = self.local_addr()?;
+        println!("[{}] WebSocket gateway listening on ws://{}", timestamp(), addr);
+        info!("WebSocket gateway listening on ws://{}", addr);
+
+        thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let fanout = Arc::clone(&fanout);
+                        thread::spawn(move || handle_ws_client(stream, fanout));
+                    }
+                    Err(e) => error!("WebSocket connection failed: {}", e),
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Serves one WebSocket client until it disconnects
+fn handle_ws_client(stream: TcpStream, fanout: Arc<FanOut>) {
+    let client = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+
+    let config = WebSocketConfig {
+        max_write_buffer_size: MAX_WRITE_BUFFER_SIZE,
+        ..WebSocketConfig::default()
+    };
+    // Рукопожатие в блокирующем режиме, дальше сокет неблокирующий
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(HANDSHAKE_TIMEOUT_SECS)));
+    let mut socket = match tungstenite::accept_with_config(stream, Some(config)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {}", client, e);
+            return;
+        }
+    };
+    if let Err(e) = socket.get_ref().set_nonblocking(true) {
+        error!("Failed to set WebSocket {} non-blocking: {}", client, e);
+        return;
+    }
+
+    let mut reader = fanout.subscribe();
+    println!("[{}] WebSocket client connected from {}", timestamp(), client);
+    info!("WebSocket client connected from {}", client);
+
+    match serve(&mut socket, &mut reader, &fanout) {
+        Ok(()) => info!("WebSocket client {} disconnected", client),
+        Err(e) => {
+            println!("[{}] WebSocket client {} stopped: {}", timestamp(), client, e);
+            info!("WebSocket client {} stopped: {}", client, e);
+        }
+    }
+}
+
+/// Pushes quotes and answers requests until the client closes the connection
+///
+/// A subscription is answered with the last quote of every added ticker known to `fanout`, right after the reply
+fn serve(socket: &mut WebSocket<TcpStream>, reader: &mut Subscriber, fanout: &FanOut) -> io::Result<()> {
+    let mut subscriptions = BTreeSet::new();
+    // Время снимка добавленного тикера: более старые котировки из очереди уже не отправляются
+    let mut snapshot_times: HashMap<String, u64> = HashMap::new();
+
+    loop {
+        // Очередь вычитываем целиком, даже без подписок: иначе она переполнится
+        let mut next = reader.recv_timeout(Duration::from_millis(REQUEST_POLL_INTERVAL_MS)).map_err(|e| match e {
+            RecvTimeoutError::Timeout => TryRecvError::Empty,
+            RecvTimeoutError::Disconnected => TryRecvError::Disconnected,
+        });
+        loop {
+            match next {
+                Ok(quote) => {
+                    let stale = match snapshot_times.get(&quote.ticker) {
+                        Some(&time) if quote.timestamp < time => true,
+                        Some(&time) if quote.timestamp > time => {
+                            snapshot_times.remove(&quote.ticker);
+                            false
+                        }
+                        _ => false,
+                    };
+                    if subscriptions.contains(&quote.ticker) && !stale {
+                        debug!("Pushing quote to WebSocket: {:?}", quote);
+                        send(socket, WsEvent::Quote(&quote).to_message())?;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) if reader.is_evicted() => {
+                    return Err(io::Error::other("fell behind and was disconnected by the overflow policy"));
+                }
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+            next = reader.try_recv();
+        }
+
+        loop {
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    let before = subscriptions.clone();
+                    let reply = apply_request(&mut subscriptions, &text);
+                    send(socket, Message::Text(reply))?;
+                    for quote in fanout.snapshot() {
+                        if subscriptions.contains(&quote.ticker) && !before.contains(&quote.ticker) {
+                            snapshot_times.insert(quote.ticker.clone(), quote.timestamp);
+                            send(socket, WsEvent::Quote(&quote).to_message())?;
+                        }
+                    }
+                }
+                // На ping и close tungstenite отвечает сам
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(e) => return Err(into_io_error(e)),
+            }
+        }
+
+        match socket.flush() {
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+            result => result.map_err(into_io_error)?,
+        }
+    }
+}
+
+/// Queues a message, a socket that cannot take it right now is written on the next flush
+fn send(socket: &mut WebSocket<TcpStream>, message: Message) -> io::Result<()> {
+    match socket.send(message) {
+        Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+        result => result.map_err(into_io_error),
+    }
+}
+
+fn into_io_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}